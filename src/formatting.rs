@@ -0,0 +1,194 @@
+//! Formats the values shown as variables. Nothing in here talks to the auto
+//! splitting runtime, so it can be tested on the host.
+
+use arrayvec::ArrayString;
+use bytemuck_derive::{Pod, Zeroable};
+
+pub const LEVEL_7_X: i32 = 14;
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct Digits {
+    pub minutes: f32,
+    pub seconds: f32,
+    pub hundredths: f32,
+}
+
+impl Digits {
+    pub fn format_into<const N: usize>(&self, string: &mut ArrayString<N>) {
+        let mut buffer = itoa::Buffer::new();
+        let _ = string.try_push_str(buffer.format(self.minutes as u32));
+        let _ = string.try_push(':');
+        let seconds = buffer.format(self.seconds as u8);
+        if seconds.len() < 2 {
+            let _ = string.try_push('0');
+        }
+        let _ = string.try_push_str(seconds);
+        let _ = string.try_push('.');
+        let hundredths = buffer.format(self.hundredths as u8);
+        if hundredths.len() < 2 {
+            let _ = string.try_push('0');
+        }
+        let _ = string.try_push_str(hundredths);
+    }
+}
+
+pub fn stage(level: i32) -> i32 {
+    ((level / 2) + 1).min(7)
+}
+
+pub fn act(level: i32) -> char {
+    if level == LEVEL_7_X {
+        'X'
+    } else if level & 1 == 0 {
+        '1'
+    } else {
+        '2'
+    }
+}
+
+pub fn level_into<const N: usize>(level: i32, string: &mut ArrayString<N>) {
+    let mut buffer = itoa::Buffer::new();
+    let _ = string.try_push_str(buffer.format(stage(level)));
+    let _ = string.try_push('-');
+    let _ = string.try_push(act(level));
+}
+
+/// The DLC's Toree bonus stages are separate from the shrine progression.
+pub fn bonus_stage(scene: &str) -> Option<&str> {
+    scene.starts_with("Toree").then_some(scene)
+}
+
+pub fn character(character: u32) -> &'static str {
+    match character {
+        0 => "Hana",
+        1 => "Toree",
+        2 => "Toukie",
+        3 => "Accel",
+        _ => "Unknown",
+    }
+}
+
+/// Joins the pieces of a single line summary, such as
+/// `4-1 | Hana | 12 deaths | 3:41.25`.
+pub struct Status<const N: usize> {
+    string: ArrayString<N>,
+}
+
+impl<const N: usize> Status<N> {
+    pub fn new() -> Self {
+        Self {
+            string: ArrayString::new(),
+        }
+    }
+
+    /// Starts the next piece, which is then written into the returned string.
+    pub fn piece(&mut self) -> &mut ArrayString<N> {
+        if !self.string.is_empty() {
+            let _ = self.string.try_push_str(" | ");
+        }
+        &mut self.string
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.string
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels() {
+        let cases = [
+            (0, "1-1"),
+            (1, "1-2"),
+            (2, "2-1"),
+            (3, "2-2"),
+            (4, "3-1"),
+            (5, "3-2"),
+            (6, "4-1"),
+            (7, "4-2"),
+            (8, "5-1"),
+            (9, "5-2"),
+            (10, "6-1"),
+            (11, "6-2"),
+            (12, "7-1"),
+            (13, "7-2"),
+            (14, "7-X"),
+        ];
+        for (level, expected) in cases {
+            let mut string = ArrayString::<8>::new();
+            level_into(level, &mut string);
+            assert_eq!(string.as_str(), expected, "level {level}");
+        }
+    }
+
+    #[test]
+    fn digits() {
+        let cases = [
+            ((0.0, 0.0, 0.0), "0:00.00"),
+            ((0.0, 5.0, 9.0), "0:05.09"),
+            ((0.0, 9.0, 10.0), "0:09.10"),
+            ((1.0, 10.0, 99.0), "1:10.99"),
+            ((12.0, 59.0, 99.0), "12:59.99"),
+            ((123.0, 0.0, 1.0), "123:00.01"),
+        ];
+        for ((minutes, seconds, hundredths), expected) in cases {
+            let mut string = ArrayString::<16>::new();
+            Digits {
+                minutes,
+                seconds,
+                hundredths,
+            }
+            .format_into(&mut string);
+            assert_eq!(string.as_str(), expected);
+        }
+    }
+
+    #[test]
+    fn bonus_stages() {
+        let cases = [
+            ("Shrine01", None),
+            ("Shrine07", None),
+            ("Toree01", Some("Toree01")),
+            ("Toree03", Some("Toree03")),
+            ("", None),
+        ];
+        for (scene, expected) in cases {
+            assert_eq!(bonus_stage(scene), expected, "scene {scene}");
+        }
+    }
+
+    #[test]
+    fn characters() {
+        let cases = [
+            (0, "Hana"),
+            (1, "Toree"),
+            (2, "Toukie"),
+            (3, "Accel"),
+            (4, "Unknown"),
+            (u32::MAX, "Unknown"),
+        ];
+        for (id, expected) in cases {
+            assert_eq!(character(id), expected);
+        }
+    }
+
+    #[test]
+    fn status() {
+        let mut status = Status::<64>::new();
+        assert_eq!(status.as_str(), "");
+        level_into(6, status.piece());
+        let _ = status.piece().try_push_str(character(0));
+        let _ = status.piece().try_push_str("12 deaths");
+        Digits {
+            minutes: 3.0,
+            seconds: 41.0,
+            hundredths: 25.0,
+        }
+        .format_into(status.piece());
+        assert_eq!(status.as_str(), "4-1 | Hana | 12 deaths | 3:41.25");
+    }
+}
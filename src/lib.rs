@@ -4,25 +4,441 @@
     feature(type_alias_impl_trait, const_async_blocks)
 )]
 
-use core::pin::pin;
+use core::{marker::PhantomData, pin::pin};
 
-use arrayvec::ArrayString;
+use arrayvec::{ArrayString, ArrayVec};
 use asr::{
     future::{next_tick, retry},
     game_engine::unity::il2cpp::{Class, Image, Module, Version},
     print_message,
+    settings::{gui::Title, Gui},
     time::Duration,
+    time_util::Instant,
     timer::{self, TimerState},
-    watcher::Watcher,
-    Address, Address64, Process,
+    watcher::{Pair, Watcher},
+    Address, Address64, Process, ProcessId,
 };
-use asr_derive::Il2cppClass;
-use bytemuck_derive::{Pod, Zeroable};
+use asr_derive::Gui;
+use bytemuck::CheckedBitPattern;
+use formatting::Digits;
 use futures_util::future::{self, Either};
+use run::{AsrTimer, Cooperative, Run};
+
+mod formatting;
+mod run;
 
 asr::panic_handler!();
 
+#[derive(Gui)]
+struct Settings {
+    /// Category preset
+    category: Category,
+    /// Only start the timer when playing as
+    start_character: StartCharacter,
+    /// Game time
+    timing: Timing,
+    /// Pause the game time during cutscenes
+    #[default = false]
+    pause_in_cutscenes: bool,
+    /// Split levels when
+    split_trigger: SplitTrigger,
+    /// Only split levels finished in order
+    ///
+    /// Finishing a level that isn't the next one in the story, such as
+    /// replaying an earlier level, doesn't split.
+    #[default = false]
+    split_in_order: bool,
+    /// Only split at the end of each stage
+    ///
+    /// The first act of each stage doesn't split, so there's one split per
+    /// stage.
+    #[default = false]
+    split_stages_only: bool,
+    /// When finishing a level a second time
+    repeated_levels: RepeatedLevels,
+    /// Split on the DLC's Toree bonus stages
+    #[default = true]
+    split_bonus_stages: bool,
+    /// When dying
+    on_death: OnDeath,
+    /// Reset the run after dying
+    reset_after_deaths: DeathLimit,
+    /// When restarting the first level
+    restart_first_level: RestartFirstLevel,
+    /// Split on entering 7-X
+    ///
+    /// Not supported with the DLC update yet.
+    #[default = false]
+    split_entering_7_x: bool,
+    /// Announce new best level times
+    ///
+    /// Shows the level in the Milestone variable for a few seconds.
+    #[default = false]
+    milestones: bool,
+    /// Publish a status summary
+    ///
+    /// Combines the level, character, deaths and game time into the Status
+    /// variable, for overlays that can only show a single variable.
+    #[default = false]
+    status: bool,
+    /// Once the credits start
+    credits: Credits,
+    /// Final game time
+    final_time: FinalTime,
+    /// Back off when something else controls the timer
+    ///
+    /// If something else, such as a second auto splitter, keeps undoing the
+    /// starts and resets of this one right away, this one stops starting and
+    /// resetting the timer for as long as that keeps happening, but keeps the
+    /// game time and variables updated.
+    #[default = false]
+    cooperate: bool,
+    /// Split on finishing
+    #[heading_level = 1]
+    levels: Title,
+    /// 1-1
+    #[default = true]
+    split_1_1: bool,
+    /// 1-2
+    #[default = true]
+    split_1_2: bool,
+    /// 2-1
+    #[default = true]
+    split_2_1: bool,
+    /// 2-2
+    #[default = true]
+    split_2_2: bool,
+    /// 3-1
+    #[default = true]
+    split_3_1: bool,
+    /// 3-2
+    #[default = true]
+    split_3_2: bool,
+    /// 4-1
+    #[default = true]
+    split_4_1: bool,
+    /// 4-2
+    #[default = true]
+    split_4_2: bool,
+    /// 5-1
+    #[default = true]
+    split_5_1: bool,
+    /// 5-2
+    #[default = true]
+    split_5_2: bool,
+    /// 6-1
+    #[default = true]
+    split_6_1: bool,
+    /// 6-2
+    #[default = true]
+    split_6_2: bool,
+    /// 7-1
+    #[default = true]
+    split_7_1: bool,
+    /// 7-2
+    #[default = true]
+    split_7_2: bool,
+    /// 7-X
+    #[default = true]
+    split_7_x: bool,
+    /// Split on entering the DLC scene
+    ///
+    /// The DLC doesn't number its levels, so these split as soon as the scene
+    /// is entered rather than on its results screen.
+    #[heading_level = 1]
+    dlc_scenes: Title,
+    /// Shrine02
+    #[default = false]
+    enter_shrine_02: bool,
+    /// Shrine03
+    #[default = false]
+    enter_shrine_03: bool,
+    /// Shrine04
+    #[default = false]
+    enter_shrine_04: bool,
+    /// Shrine05
+    #[default = false]
+    enter_shrine_05: bool,
+    /// Shrine06
+    #[default = false]
+    enter_shrine_06: bool,
+    /// Shrine07
+    #[default = false]
+    enter_shrine_07: bool,
+    /// Toree01
+    #[default = false]
+    enter_toree_01: bool,
+    /// Toree02
+    #[default = false]
+    enter_toree_02: bool,
+    /// Toree03
+    #[default = false]
+    enter_toree_03: bool,
+}
+
+impl Settings {
+    fn split_level(&self, level: i32) -> bool {
+        match self.category {
+            Category::Custom => {}
+            Category::AnyPercent => return level <= LevelOrScene::LEVEL_7_2,
+            Category::AllStages => return true,
+        }
+        match level {
+            0 => self.split_1_1,
+            1 => self.split_1_2,
+            2 => self.split_2_1,
+            3 => self.split_2_2,
+            4 => self.split_3_1,
+            5 => self.split_3_2,
+            6 => self.split_4_1,
+            7 => self.split_4_2,
+            8 => self.split_5_1,
+            9 => self.split_5_2,
+            10 => self.split_6_1,
+            11 => self.split_6_2,
+            12 => self.split_7_1,
+            13 => self.split_7_2,
+            14 => self.split_7_x,
+            _ => true,
+        }
+    }
+
+    fn split_entering_scene(&self, scene: &str) -> bool {
+        if !matches!(self.category, Category::Custom) {
+            return false;
+        }
+        match scene {
+            "Shrine02" => self.enter_shrine_02,
+            "Shrine03" => self.enter_shrine_03,
+            "Shrine04" => self.enter_shrine_04,
+            "Shrine05" => self.enter_shrine_05,
+            "Shrine06" => self.enter_shrine_06,
+            "Shrine07" => self.enter_shrine_07,
+            "Toree01" => self.enter_toree_01,
+            "Toree02" => self.enter_toree_02,
+            "Toree03" => self.enter_toree_03,
+            _ => false,
+        }
+    }
+
+    /// Whether finishing the level splits with the current settings.
+    fn splits_level(&self, level: i32) -> bool {
+        self.split_level(level) && self.split_act(level)
+    }
+
+    fn split_act(&self, level: i32) -> bool {
+        !(matches!(self.category, Category::Custom) && self.split_stages_only)
+            || formatting::act(level) != '1'
+    }
+
+    fn split_bonus_stages(&self) -> bool {
+        match self.category {
+            Category::Custom => self.split_bonus_stages,
+            Category::AnyPercent => false,
+            Category::AllStages => true,
+        }
+    }
+
+    fn split_entering_7_x(&self) -> bool {
+        matches!(self.category, Category::Custom) && self.split_entering_7_x
+    }
+}
+
+/// Presets that override which levels, acts, bonus stages and scenes split to
+/// match the leaderboard categories. Any% ends with the credits after 7-2,
+/// while All Stages also splits 7-X and the DLC's bonus stages.
+#[derive(Gui)]
+enum Category {
+    /// Custom
+    #[default]
+    Custom,
+    /// Any%
+    AnyPercent,
+    /// All Stages
+    AllStages,
+}
+
+#[derive(Gui)]
+enum StartCharacter {
+    /// Any character
+    #[default]
+    Any,
+    /// Hana
+    Hana,
+    /// Toree
+    Toree,
+    /// Toukie
+    Toukie,
+    /// Accel
+    Accel,
+}
+
+impl StartCharacter {
+    fn allows(&self, character: u32) -> bool {
+        let expected = match self {
+            StartCharacter::Any => return true,
+            StartCharacter::Hana => 0,
+            StartCharacter::Toree => 1,
+            StartCharacter::Toukie => 2,
+            StartCharacter::Accel => 3,
+        };
+        character == expected
+    }
+}
+
+#[derive(Gui)]
+enum Timing {
+    /// Sum of the level timers
+    #[default]
+    LevelTime,
+    /// Sum of the times on the results screens
+    ResultTimes,
+    /// Real time without loads
+    RealTimeWithoutLoads,
+}
+
+#[derive(Gui)]
+enum SplitTrigger {
+    /// The results screen appears
+    #[default]
+    Results,
+    /// The results screen is dismissed
+    ResultsExit,
+    /// The level timer stops (last input)
+    TimerStopped,
+}
+
+impl SplitTrigger {
+    /// The state of the game that the finished level is judged by. Once the
+    /// results screen is dismissed, the game might already be moving on to
+    /// the next level.
+    fn finished<'a>(&self, game_manager: &'a Pair<GameManager>) -> &'a GameManager {
+        match self {
+            SplitTrigger::ResultsExit => &game_manager.old,
+            SplitTrigger::Results | SplitTrigger::TimerStopped => &game_manager.current,
+        }
+    }
+
+    fn is_level_finished(&self, game_manager: &Pair<GameManager>, timer: &Pair<Timer>) -> bool {
+        match self {
+            SplitTrigger::Results => game_manager.check(|g| g.game_state == game_state::RESULTS),
+            SplitTrigger::ResultsExit => {
+                game_manager.old.game_state == game_state::RESULTS
+                    && game_manager.current.game_state != game_state::RESULTS
+            }
+            SplitTrigger::TimerStopped => {
+                game_manager.game_state == game_state::MISSION && timer.check(|t| t.timer_stopped)
+            }
+        }
+    }
+}
+
+#[derive(Gui)]
+enum RepeatedLevels {
+    /// Split again
+    #[default]
+    Split,
+    /// Don't split
+    Ignore,
+}
+
+#[derive(Gui)]
+enum OnDeath {
+    /// Do nothing
+    #[default]
+    Nothing,
+    /// Split
+    Split,
+    /// Reset
+    Reset,
+    /// Undo the split of the current level
+    UndoSplit,
+}
+
+#[derive(Gui)]
+enum DeathLimit {
+    /// Never
+    #[default]
+    Never,
+    /// Once
+    Once,
+    /// 3 times
+    Three,
+    /// 5 times
+    Five,
+    /// 10 times
+    Ten,
+}
+
+impl DeathLimit {
+    fn is_reached(&self, deaths: u32) -> bool {
+        let limit = match self {
+            DeathLimit::Never => return false,
+            DeathLimit::Once => 1,
+            DeathLimit::Three => 3,
+            DeathLimit::Five => 5,
+            DeathLimit::Ten => 10,
+        };
+        deaths >= limit
+    }
+}
+
+#[derive(Gui)]
+enum RestartFirstLevel {
+    /// Reset
+    #[default]
+    Reset,
+    /// Reset if the points and deaths are back to zero
+    ResetFromScratch,
+    /// Keep the run going
+    KeepRunning,
+}
+
+impl RestartFirstLevel {
+    fn resets(&self, game_manager: &GameManager) -> bool {
+        match self {
+            RestartFirstLevel::Reset => true,
+            RestartFirstLevel::ResetFromScratch => {
+                game_manager.points == 0 && game_manager.deaths == 0
+            }
+            RestartFirstLevel::KeepRunning => false,
+        }
+    }
+}
+
+#[derive(Gui)]
+enum Credits {
+    /// Split and stop the game time
+    #[default]
+    Stop,
+    /// Only keep the variables updated
+    VariablesOnly,
+}
+
+#[derive(Gui)]
+enum FinalTime {
+    /// Exact
+    #[default]
+    Exact,
+    /// Truncated to hundredths
+    Floor,
+    /// Rounded to hundredths
+    Round,
+}
+
+impl FinalTime {
+    fn apply(&self, time: Duration) -> Duration {
+        let hundredths = match self {
+            FinalTime::Exact => return time,
+            FinalTime::Floor => time.whole_milliseconds() / 10,
+            FinalTime::Round => (time.whole_milliseconds() + 5) / 10,
+        };
+        Duration::milliseconds(hundredths as i64 * 10)
+    }
+}
+
 struct GameInfo {
+    game_assembly: Option<Address>,
     timer_instance: Address,
     game_manager_instance: Address,
     timer_class: TimerBinding,
@@ -30,7 +446,7 @@ struct GameInfo {
 }
 
 impl GameInfo {
-    async fn load(process: &Process) -> Self {
+    async fn load(process: &Process, is_demo: bool) -> Self {
         let module = Module::wait_attach(process, Version::V2020).await;
 
         print_message("Found Mono");
@@ -39,67 +455,450 @@ impl GameInfo {
 
         print_message("Found Assembly-CSharp");
 
-        let game_manager_class = GameManagerBinding::bind(process, &module, &image).await;
-        let game_manager_instance = game_manager_class
-            .class()
-            .wait_get_static_instance(process, &module, "<Instance>k__BackingField")
-            .await;
+        let game_assembly = process.get_module_address("GameAssembly.dll").ok();
 
-        print_message(if game_manager_class.is_dlc() {
-            "Found GameManager (DLC)"
-        } else {
-            "Found GameManager (No DLC)"
+        detect_build(process);
+
+        let game_manager_class = GameManagerBinding::bind(process, &module, &image, is_demo).await;
+        let game_manager_instance =
+            wait_get_instance(process, &module, game_manager_class.class()).await;
+
+        print_message(match game_manager_class {
+            GameManagerBinding::Original(_) => "Found GameManager (No DLC)",
+            GameManagerBinding::Dlc(_) => "Found GameManager (DLC)",
+            GameManagerBinding::Demo(_) => "Found GameManager (Demo)",
         });
 
-        let timer_class = Timer::bind(process, &module, &image).await;
-        let timer_instance = timer_class
-            .class()
-            .wait_get_static_instance(
-                process,
-                &module,
-                if game_manager_class.is_dlc() {
-                    "<Instance>k__BackingField"
-                } else {
-                    "_instance"
-                },
-            )
-            .await;
+        let timer_class = TimerBinding::bind(process, &module, &image).await;
+        let timer_instance = wait_get_instance(process, &module, timer_class.class()).await;
 
         print_message("Found Timer");
 
         Self {
+            game_assembly,
             timer_instance,
             game_manager_instance,
             timer_class,
             game_manager_class,
         }
     }
+
+    /// The Unity player can be recreated without the process exiting, which
+    /// reloads the game's code and invalidates everything that was bound.
+    /// That either moves `GameAssembly.dll` or makes the instances unreadable.
+    fn is_outdated(&self, process: &Process, reads_failing: bool) -> bool {
+        reads_failing
+            || process
+                .get_module_address("GameAssembly.dll")
+                .is_ok_and(|address| Some(address) != self.game_assembly)
+    }
+
+    fn read_game_manager(&self, process: &Process) -> Result<GameManager, ()> {
+        self.game_manager_class
+            .read(process, self.game_manager_instance)
+    }
+}
+
+/// Publishes the size of `GameAssembly.dll`, which tells the builds of the
+/// game apart.
+fn detect_build(process: &Process) {
+    let Ok(size) = process.get_module_size("GameAssembly.dll") else {
+        timer::set_variable("Build", "Unknown");
+        return;
+    };
+
+    let mut buffer = itoa::Buffer::new();
+    let mut string = ArrayString::<48>::new();
+    let _ = string.try_push_str(buffer.format(size));
+    let _ = string.try_push_str(" bytes");
+    timer::set_variable("Build", &string);
+}
+
+/// The names the singleton instance of a class has been stored under.
+const INSTANCE_NAMES: &[&str] = &["<Instance>k__BackingField", "_instance", "instance"];
+
+fn get_instance(process: &Process, module: &Module, class: &Class) -> Option<Address> {
+    INSTANCE_NAMES
+        .iter()
+        .find_map(|name| class.get_static_instance(process, module, name))
+}
+
+async fn wait_get_instance(process: &Process, module: &Module, class: &Class) -> Address {
+    retry(|| get_instance(process, module, class)).await
+}
+
+/// A field that got renamed between builds of the game. The names are tried in
+/// order and binding waits until one of them shows up.
+struct Field<T> {
+    offset: u64,
+    _type: PhantomData<T>,
+}
+
+impl<T: CheckedBitPattern> Field<T> {
+    async fn bind(process: &Process, module: &Module, class: &Class, names: &[&str]) -> Self {
+        let offset = retry(|| {
+            names
+                .iter()
+                .find_map(|name| class.get_field(process, module, name))
+        })
+        .await;
+        Self {
+            offset: offset as u64,
+            _type: PhantomData,
+        }
+    }
+
+    fn read(&self, process: &Process, instance: Address) -> Result<T, ()> {
+        process.read(instance + self.offset).map_err(drop)
+    }
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Pod, Zeroable)]
-struct Digits {
-    minutes: f32,
-    seconds: f32,
-    hundredths: f32,
+/// A field that not every build of the game has. Unlike [`Field`], binding it
+/// doesn't wait for the field to show up, so a missing field only
+/// disables the feature relying on it. That gets logged, so it's clear why a
+/// variable stays empty.
+struct OptionalField<T> {
+    offset: Option<u64>,
+    _type: PhantomData<T>,
+}
+
+impl<T: CheckedBitPattern> OptionalField<T> {
+    fn bind(process: &Process, module: &Module, class: &Class, names: &[&str]) -> Self {
+        let offset = names
+            .iter()
+            .find_map(|name| class.get_field(process, module, name))
+            .map(|offset| offset as u64);
+        if offset.is_none() {
+            let mut message = ArrayString::<64>::new();
+            let _ = message.try_push_str("Couldn't find the field ");
+            let _ = message.try_push_str(names.first().copied().unwrap_or_default());
+            print_message(&message);
+        }
+        Self {
+            offset,
+            _type: PhantomData,
+        }
+    }
+
+    fn read(&self, process: &Process, instance: Address) -> Option<T> {
+        process.read(instance + self.offset?).ok()
+    }
 }
 
 impl Digits {
-    fn format_into<const N: usize>(&self, string: &mut ArrayString<N>) {
+    fn duration(&self) -> Duration {
+        Duration::seconds(self.minutes as i64 * 60 + self.seconds as i64)
+            + Duration::milliseconds(self.hundredths as i64 * 10)
+    }
+}
+
+impl From<Duration> for Digits {
+    fn from(duration: Duration) -> Self {
+        let hundredths = duration.whole_milliseconds() / 10;
+        Self {
+            minutes: (hundredths / 6000) as f32,
+            seconds: (hundredths / 100 % 60) as f32,
+            hundredths: (hundredths % 100) as f32,
+        }
+    }
+}
+
+/// Holds on to the last value that was read successfully for a few ticks, so a
+/// momentary read failure doesn't cause a transition to be missed.
+struct HoldLast<T> {
+    last: Option<T>,
+    failed_reads: u32,
+}
+
+impl<T> Default for HoldLast<T> {
+    fn default() -> Self {
+        Self {
+            last: None,
+            failed_reads: 0,
+        }
+    }
+}
+
+impl<T: Copy> HoldLast<T> {
+    /// A quarter of a second at the regular tick rate.
+    const MAX_FAILED_READS: u32 = 30;
+
+    fn update(&mut self, value: Option<T>) -> Option<T> {
+        if value.is_some() {
+            self.last = value;
+            self.failed_reads = 0;
+        } else {
+            self.failed_reads = self.failed_reads.saturating_add(1);
+            if self.failed_reads > Self::MAX_FAILED_READS {
+                self.last = None;
+            }
+        }
+        self.last
+    }
+
+    fn is_stale(&self) -> bool {
+        self.failed_reads > 0 && self.last.is_some()
+    }
+
+    fn is_lost(&self) -> bool {
+        self.failed_reads > Self::MAX_FAILED_READS
+    }
+}
+
+/// Tracks how often and for how long the game has been paused during the run.
+#[derive(Default)]
+struct Pauses {
+    count: u32,
+    total: Duration,
+    paused_since: Option<Instant>,
+}
+
+impl Pauses {
+    fn update(&mut self, paused: bool) {
+        match (self.paused_since, paused) {
+            (None, true) => {
+                self.count += 1;
+                self.paused_since = Some(Instant::now());
+            }
+            (Some(since), false) => {
+                self.total += since.elapsed();
+                self.paused_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    fn total(&self) -> Duration {
+        match self.paused_since {
+            Some(since) => self.total + since.elapsed(),
+            None => self.total,
+        }
+    }
+
+    fn set_variables<const N: usize>(
+        &self,
+        string: &mut ArrayString<N>,
+        variables: &mut Variables,
+    ) {
         let mut buffer = itoa::Buffer::new();
-        let _ = string.try_push_str(buffer.format(self.minutes as u32));
-        let _ = string.try_push(':');
-        let seconds = buffer.format(self.seconds as u8);
-        if seconds.len() < 2 {
-            let _ = string.try_push('0');
+        variables.set(
+            "Paused",
+            if self.paused_since.is_some() {
+                "Yes"
+            } else {
+                "No"
+            },
+        );
+        variables.set("Pauses", buffer.format(self.count));
+        string.clear();
+        Digits::from(self.total()).format_into(string);
+        variables.set("Pause Time", string);
+    }
+}
+
+/// Tracks how long each game state has been active in the current level.
+#[derive(Default)]
+struct StateTimes {
+    times: [Duration; 8],
+    current: Option<(i32, Instant)>,
+}
+
+impl StateTimes {
+    fn update(&mut self, state: i32, level_changed: bool) {
+        if level_changed {
+            self.times = Default::default();
+            self.current = None;
+        }
+        if let Some((old_state, since)) = self.current {
+            if old_state == state {
+                return;
+            }
+            if let Some(time) = self.times.get_mut(old_state as usize) {
+                *time += since.elapsed();
+            }
+        }
+        self.current = Some((state, Instant::now()));
+    }
+
+    /// The variables holding the time of each state, in the order of the
+    /// states.
+    const VARIABLES: [&'static str; 8] = [
+        "Mission Time",
+        "Title Screen Time",
+        "Menu Time",
+        "Cutscene Time",
+        "Death Time",
+        "Respawn Time",
+        "Results Time",
+        "Load Time",
+    ];
+
+    fn get(&self, state: i32) -> Duration {
+        let time = self.times.get(state as usize).copied().unwrap_or_default();
+        match self.current {
+            Some((current, since)) if current == state => time + since.elapsed(),
+            _ => time,
+        }
+    }
+
+    fn current(&self) -> Duration {
+        self.current
+            .map_or(Duration::ZERO, |(state, _)| self.get(state))
+    }
+
+    fn set_variables<const N: usize>(
+        &self,
+        string: &mut ArrayString<N>,
+        variables: &mut Variables,
+    ) {
+        string.clear();
+        Digits::from(self.current()).format_into(string);
+        variables.set("Time In State", string);
+        for (state, key) in (0..).zip(Self::VARIABLES) {
+            string.clear();
+            Digits::from(self.get(state)).format_into(string);
+            variables.set(key, string);
+        }
+    }
+}
+
+/// The levels that have been finished during the current run.
+#[derive(Default)]
+struct CompletedLevels {
+    levels: u32,
+    scenes: ArrayVec<ArrayString<16>, 32>,
+}
+
+impl CompletedLevels {
+    /// Returns whether the level hasn't been finished before.
+    fn insert(&mut self, level_or_scene: &LevelOrScene) -> bool {
+        match level_or_scene {
+            LevelOrScene::Level(level) => {
+                let bit = 1u32.checked_shl(*level as u32).unwrap_or_default();
+                let is_new = self.levels & bit == 0;
+                self.levels |= bit;
+                is_new
+            }
+            LevelOrScene::Scene(scene) => {
+                if self.scenes.contains(scene) {
+                    return false;
+                }
+                let _ = self.scenes.try_push(*scene);
+                true
+            }
+        }
+    }
+
+    fn remove(&mut self, level_or_scene: &LevelOrScene) {
+        match level_or_scene {
+            LevelOrScene::Level(level) => {
+                self.levels &= !1u32.checked_shl(*level as u32).unwrap_or_default();
+            }
+            LevelOrScene::Scene(scene) => self.scenes.retain(|s| s != scene),
+        }
+    }
+}
+
+/// The best time of each level seen since the auto splitter was started.
+#[derive(Default)]
+struct BestLevelTimes {
+    times: [Option<Duration>; formatting::LEVEL_7_X as usize + 1],
+}
+
+/// A message that is shown for a few seconds after something noteworthy
+/// happened.
+#[derive(Default)]
+struct Milestone {
+    text: ArrayString<16>,
+    since: Option<Instant>,
+}
+
+impl Milestone {
+    const DURATION: Duration = Duration::seconds(5);
+
+    fn gold(&mut self, level: i32) {
+        self.text.clear();
+        let _ = self.text.try_push_str("GOLD ");
+        formatting::level_into(level, &mut self.text);
+        self.since = Some(Instant::now());
+    }
+
+    fn text(&self) -> Option<&str> {
+        (self.since?.elapsed() < Self::DURATION).then_some(&self.text)
+    }
+}
+
+/// Lets something happen at most once per interval.
+#[derive(Default)]
+struct Throttle {
+    last: Option<Instant>,
+}
+
+impl Throttle {
+    fn ready(&mut self, interval: Duration) -> bool {
+        if self.last.is_some_and(|last| last.elapsed() < interval) {
+            return false;
+        }
+        self.last = Some(Instant::now());
+        true
+    }
+}
+
+/// How much of the run is left, estimated from the best level times.
+struct Remaining {
+    levels: i32,
+    estimate: Option<Duration>,
+}
+
+impl BestLevelTimes {
+    /// Returns whether the time beats a previous best time.
+    fn record(&mut self, level: i32, time: Duration) -> bool {
+        let Some(slot) = self.times.get_mut(level as usize) else {
+            return false;
+        };
+        match slot {
+            Some(best) if *best <= time => false,
+            Some(_) => {
+                *slot = Some(time);
+                true
+            }
+            None => {
+                *slot = Some(time);
+                false
+            }
         }
-        let _ = string.try_push_str(seconds);
-        let _ = string.try_push('.');
-        let hundredths = buffer.format(self.hundredths as u8);
-        if hundredths.len() < 2 {
-            let _ = string.try_push('0');
+    }
+
+    /// Estimates the final time by assuming the current level and all the ones
+    /// after it are finished with their best times. Only the levels that split
+    /// count as remaining, both for the number of levels and the estimate.
+    /// Outside the levels there's nothing to estimate.
+    fn remaining(
+        &self,
+        settings: &Settings,
+        level: i32,
+        run_time: Duration,
+        level_time: Duration,
+    ) -> Option<Remaining> {
+        if !(LevelOrScene::LEVEL_1_1..=formatting::LEVEL_7_X).contains(&level) {
+            return None;
         }
-        let _ = string.try_push_str(hundredths);
+        let levels =
+            || (level..=formatting::LEVEL_7_X).filter(|&level| settings.splits_level(level));
+        let estimate = levels().try_fold(run_time, |sum, remaining| {
+            let best = self.times[remaining as usize]?;
+            Some(
+                sum + if remaining == level {
+                    best.max(level_time)
+                } else {
+                    best
+                },
+            )
+        });
+        Some(Remaining {
+            levels: levels().count() as i32,
+            estimate,
+        })
     }
 }
 
@@ -111,7 +910,7 @@ struct GameManager {
     level_or_scene: LevelOrScene,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 enum LevelOrScene {
     Level(i32),
     Scene(ArrayString<16>),
@@ -121,49 +920,51 @@ impl LevelOrScene {
     const LEVEL_1_1: i32 = 0;
     const LEVEL_2_1: i32 = 2;
     const LEVEL_7_2: i32 = 13;
-    const LEVEL_7_X: i32 = 14;
 
-    fn is_in_first_level(&self) -> bool {
+    fn level(&self) -> Option<i32> {
         match self {
-            LevelOrScene::Level(v) => *v == Self::LEVEL_1_1,
-            LevelOrScene::Scene(s) => s == "Shrine01",
+            LevelOrScene::Level(level) => Some(*level),
+            LevelOrScene::Scene(_) => None,
         }
     }
 
-    fn stage(level: i32) -> i32 {
-        ((level / 2) + 1).min(7)
-    }
-
-    fn act(level: i32) -> char {
-        if level == Self::LEVEL_7_X {
-            'X'
-        } else if level & 1 == 0 {
-            '1'
-        } else {
-            '2'
+    fn is_in_first_level(&self) -> bool {
+        match self {
+            LevelOrScene::Level(v) => *v == Self::LEVEL_1_1,
+            LevelOrScene::Scene(s) => s == "Shrine01",
         }
     }
 
-    fn format_level_into<const N: usize>(level: i32, string: &mut ArrayString<N>) {
-        let mut buffer = itoa::Buffer::new();
-        let _ = string.try_push_str(buffer.format(Self::stage(level)));
-        let _ = string.try_push('-');
-        let _ = string.try_push(Self::act(level));
-    }
-
-    fn set_variable<const N: usize>(&self, string: &mut ArrayString<N>) {
+    fn set_variable<const N: usize>(&self, string: &mut ArrayString<N>, variables: &mut Variables) {
         match self {
             LevelOrScene::Level(level) => {
                 string.clear();
-                Self::format_level_into(*level, string);
-                timer::set_variable("Level", string);
+                formatting::level_into(*level, string);
+                variables.set("Level", string);
             }
             LevelOrScene::Scene(scene) => {
-                timer::set_variable("Scene", scene);
+                variables.set("Scene", scene);
+                variables.set("Bonus Stage", self.bonus_stage().unwrap_or("-"));
             }
         }
     }
 
+    fn bonus_stage(&self) -> Option<&str> {
+        match self {
+            LevelOrScene::Level(_) => None,
+            LevelOrScene::Scene(scene) => formatting::bonus_stage(scene),
+        }
+    }
+
+    /// The DLC's name for the scene of 7-X isn't known, so it's only
+    /// recognized by its level.
+    fn is_in_7_x(&self) -> bool {
+        match self {
+            LevelOrScene::Level(level) => *level == formatting::LEVEL_7_X,
+            LevelOrScene::Scene(_) => false,
+        }
+    }
+
     fn is_in_final_level(&self) -> bool {
         match self {
             LevelOrScene::Level(level) => *level >= Self::LEVEL_7_2,
@@ -182,12 +983,19 @@ impl LevelOrScene {
 enum GameManagerBinding {
     Original(original::GameManagerBinding),
     Dlc(dlc::GameManagerBinding),
+    Demo(demo::GameManagerBinding),
 }
 
 impl GameManagerBinding {
-    async fn bind(process: &Process, module: &Module, image: &Image) -> Self {
-        let original = pin!(original::GameManager::bind(process, module, image));
-        let dlc = pin!(dlc::GameManager::bind(process, module, image));
+    /// The demo is always its own layout. For the full game, both the
+    /// original and the DLC layout are tried and whichever binds first is
+    /// used.
+    async fn bind(process: &Process, module: &Module, image: &Image, is_demo: bool) -> Self {
+        if is_demo {
+            return Self::Demo(demo::GameManagerBinding::bind(process, module, image).await);
+        }
+        let original = pin!(original::GameManagerBinding::bind(process, module, image));
+        let dlc = pin!(dlc::GameManagerBinding::bind(process, module, image));
         match future::select(original, dlc).await {
             Either::Left((original, _)) => Self::Original(original),
             Either::Right((dlc, _)) => Self::Dlc(dlc),
@@ -198,14 +1006,10 @@ impl GameManagerBinding {
         match self {
             GameManagerBinding::Original(original) => original.class(),
             GameManagerBinding::Dlc(dlc) => dlc.class(),
+            GameManagerBinding::Demo(demo) => demo.class(),
         }
     }
 
-    #[must_use]
-    fn is_dlc(&self) -> bool {
-        matches!(self, Self::Dlc(..))
-    }
-
     fn read(&self, process: &Process, game_manager_instance: Address) -> Result<GameManager, ()> {
         Ok(match self {
             GameManagerBinding::Original(original) => {
@@ -219,63 +1023,332 @@ impl GameManagerBinding {
             }
             GameManagerBinding::Dlc(dlc) => {
                 let game_manager = dlc.read(process, game_manager_instance)?;
+                // A scene that can't be read would look like leaving the scene
+                // and entering it again.
+                let scene = read_string(process, game_manager.current_scene_ptr)
+                    .filter(|scene: &ArrayString<16>| !scene.is_empty())
+                    .ok_or(())?;
                 GameManager {
                     game_state: game_manager.game_state,
                     points: game_manager.points,
                     deaths: game_manager.deaths,
-                    level_or_scene: LevelOrScene::Scene(
-                        read_string(process, game_manager.current_scene_ptr).unwrap_or_default(),
-                    ),
+                    level_or_scene: LevelOrScene::Scene(scene),
+                }
+            }
+            GameManagerBinding::Demo(demo) => {
+                let game_manager = demo.read(process, game_manager_instance)?;
+                GameManager {
+                    game_state: game_manager.game_state,
+                    points: game_manager.points,
+                    deaths: game_manager.deaths.unwrap_or_default(),
+                    level_or_scene: LevelOrScene::Level(game_manager.level),
                 }
             }
         })
     }
 }
 
+/// The names the fields of the game manager have had. Most of them are shared
+/// between the original game and the DLC update. The game state turned into a
+/// property with the DLC update, which is what tells the two layouts apart, so
+/// its names must not overlap.
+mod game_manager_fields {
+    pub const ORIGINAL_GAME_STATE: &[&str] = &["gameState", "_gameState"];
+    pub const DLC_GAME_STATE: &[&str] = &["<GameState>k__BackingField"];
+    pub const POINTS: &[&str] = &["_points", "points", "<Points>k__BackingField"];
+    pub const DEATHS: &[&str] = &["_deaths", "deaths", "<Deaths>k__BackingField"];
+    pub const LEVEL: &[&str] = &[
+        "currentLevel",
+        "_currentLevel",
+        "<CurrentLevel>k__BackingField",
+    ];
+}
+
 mod original {
-    use asr_derive::Il2cppClass;
+    use asr::{
+        game_engine::unity::il2cpp::{Class, Image, Module},
+        Address, Process,
+    };
+
+    use crate::{game_manager_fields, Field};
 
-    #[derive(Copy, Clone, Il2cppClass)]
     pub struct GameManager {
-        #[rename = "gameState"]
         pub game_state: i32,
-        #[rename = "_points"]
         pub points: i32,
-        #[rename = "_deaths"]
         pub deaths: i32,
-        #[rename = "currentLevel"]
         pub level: i32,
     }
+
+    pub struct GameManagerBinding {
+        class: Class,
+        game_state: Field<i32>,
+        points: Field<i32>,
+        deaths: Field<i32>,
+        level: Field<i32>,
+    }
+
+    impl GameManagerBinding {
+        pub async fn bind(process: &Process, module: &Module, image: &Image) -> Self {
+            let class = image.wait_get_class(process, module, "GameManager").await;
+            let game_state = Field::bind(
+                process,
+                module,
+                &class,
+                game_manager_fields::ORIGINAL_GAME_STATE,
+            )
+            .await;
+            let points = Field::bind(process, module, &class, game_manager_fields::POINTS).await;
+            let deaths = Field::bind(process, module, &class, game_manager_fields::DEATHS).await;
+            let level = Field::bind(process, module, &class, game_manager_fields::LEVEL).await;
+            Self {
+                class,
+                game_state,
+                points,
+                deaths,
+                level,
+            }
+        }
+
+        pub fn class(&self) -> &Class {
+            &self.class
+        }
+
+        pub fn read(&self, process: &Process, instance: Address) -> Result<GameManager, ()> {
+            Ok(GameManager {
+                game_state: self.game_state.read(process, instance)?,
+                points: self.points.read(process, instance)?,
+                deaths: self.deaths.read(process, instance)?,
+                level: self.level.read(process, instance)?,
+            })
+        }
+    }
 }
 
 mod dlc {
-    use asr::Address64;
-    use asr_derive::Il2cppClass;
+    use asr::{
+        game_engine::unity::il2cpp::{Class, Image, Module},
+        Address, Address64, Process,
+    };
+
+    use crate::{game_manager_fields, Field};
 
-    #[derive(Copy, Clone, Il2cppClass)]
     pub struct GameManager {
-        #[rename = "<GameState>k__BackingField"]
         pub game_state: i32,
-        #[rename = "_points"]
         pub points: i32,
-        #[rename = "_deaths"]
         pub deaths: i32,
-        #[rename = "_currentScene"]
         pub current_scene_ptr: Address64,
     }
+
+    pub struct GameManagerBinding {
+        class: Class,
+        game_state: Field<i32>,
+        points: Field<i32>,
+        deaths: Field<i32>,
+        current_scene_ptr: Field<Address64>,
+    }
+
+    impl GameManagerBinding {
+        pub async fn bind(process: &Process, module: &Module, image: &Image) -> Self {
+            let class = image.wait_get_class(process, module, "GameManager").await;
+            let game_state =
+                Field::bind(process, module, &class, game_manager_fields::DLC_GAME_STATE).await;
+            let points = Field::bind(process, module, &class, game_manager_fields::POINTS).await;
+            let deaths = Field::bind(process, module, &class, game_manager_fields::DEATHS).await;
+            let current_scene_ptr = Field::bind(
+                process,
+                module,
+                &class,
+                &[
+                    "_currentScene",
+                    "currentScene",
+                    "<CurrentScene>k__BackingField",
+                ],
+            )
+            .await;
+            Self {
+                class,
+                game_state,
+                points,
+                deaths,
+                current_scene_ptr,
+            }
+        }
+
+        pub fn class(&self) -> &Class {
+            &self.class
+        }
+
+        pub fn read(&self, process: &Process, instance: Address) -> Result<GameManager, ()> {
+            Ok(GameManager {
+                game_state: self.game_state.read(process, instance)?,
+                points: self.points.read(process, instance)?,
+                deaths: self.deaths.read(process, instance)?,
+                current_scene_ptr: self.current_scene_ptr.read(process, instance)?,
+            })
+        }
+    }
+}
+
+/// The demo has been updated separately from the full game, so it's bound on
+/// its own instead of racing the other layouts. Only what's needed for
+/// splitting is required, the death counter is optional.
+mod demo {
+    use asr::{
+        game_engine::unity::il2cpp::{Class, Image, Module},
+        Address, Process,
+    };
+
+    use crate::{game_manager_fields, Field, OptionalField};
+
+    pub struct GameManager {
+        pub game_state: i32,
+        pub points: i32,
+        pub deaths: Option<i32>,
+        pub level: i32,
+    }
+
+    pub struct GameManagerBinding {
+        class: Class,
+        game_state: Field<i32>,
+        points: Field<i32>,
+        deaths: OptionalField<i32>,
+        level: Field<i32>,
+    }
+
+    impl GameManagerBinding {
+        pub async fn bind(process: &Process, module: &Module, image: &Image) -> Self {
+            let class = image.wait_get_class(process, module, "GameManager").await;
+            let game_state = Field::bind(
+                process,
+                module,
+                &class,
+                game_manager_fields::ORIGINAL_GAME_STATE,
+            )
+            .await;
+            let points = Field::bind(process, module, &class, game_manager_fields::POINTS).await;
+            let level = Field::bind(process, module, &class, game_manager_fields::LEVEL).await;
+            let deaths = OptionalField::bind(process, module, &class, game_manager_fields::DEATHS);
+            Self {
+                class,
+                game_state,
+                points,
+                deaths,
+                level,
+            }
+        }
+
+        pub fn class(&self) -> &Class {
+            &self.class
+        }
+
+        pub fn read(&self, process: &Process, instance: Address) -> Result<GameManager, ()> {
+            Ok(GameManager {
+                game_state: self.game_state.read(process, instance)?,
+                points: self.points.read(process, instance)?,
+                deaths: self.deaths.read(process, instance),
+                level: self.level.read(process, instance)?,
+            })
+        }
+    }
+}
+
+/// The timer's fields might turn into properties in a future patch, like the
+/// game state of the DLC's game manager did, so both kinds of names are tried.
+struct TimerBinding {
+    class: Class,
+    level_time: Field<f32>,
+    level_time_vector: Field<Digits>,
+    timer_stopped: Field<bool>,
+    character: Field<u32>,
+}
+
+impl TimerBinding {
+    async fn bind(process: &Process, module: &Module, image: &Image) -> Self {
+        let class = image.wait_get_class(process, module, "Timer").await;
+        let level_time = Field::bind(
+            process,
+            module,
+            &class,
+            &["currentLevelTime", "<CurrentLevelTime>k__BackingField"],
+        )
+        .await;
+        let level_time_vector = Field::bind(
+            process,
+            module,
+            &class,
+            &[
+                "currentLevelTimeVector",
+                "<CurrentLevelTimeVector>k__BackingField",
+            ],
+        )
+        .await;
+        let timer_stopped = Field::bind(
+            process,
+            module,
+            &class,
+            &["timerStopped", "<TimerStopped>k__BackingField"],
+        )
+        .await;
+        let character = Field::bind(
+            process,
+            module,
+            &class,
+            &["character", "<Character>k__BackingField"],
+        )
+        .await;
+        Self {
+            class,
+            level_time,
+            level_time_vector,
+            timer_stopped,
+            character,
+        }
+    }
+
+    fn class(&self) -> &Class {
+        &self.class
+    }
+
+    fn read(&self, process: &Process, instance: Address) -> Result<Timer, ()> {
+        Ok(Timer {
+            level_time: self.level_time.read(process, instance)?,
+            level_time_vector: self.level_time_vector.read(process, instance)?,
+            timer_stopped: self.timer_stopped.read(process, instance)?,
+            character: self.character.read(process, instance)?,
+        })
+    }
 }
 
-#[derive(Copy, Clone, Il2cppClass)]
+#[derive(Copy, Clone)]
 struct Timer {
-    #[rename = "currentLevelTime"]
     level_time: f32,
-    #[rename = "currentLevelTimeVector"]
     level_time_vector: Digits,
-    #[rename = "timerStopped"]
     timer_stopped: bool,
     character: u32,
 }
 
+impl Timer {
+    /// How far apart the level time in seconds and the displayed digits may be
+    /// before they are considered to disagree.
+    const MAX_LEVEL_TIME_MISMATCH: f32 = 0.1;
+
+    fn is_level_time_mismatched(&self) -> bool {
+        let difference = self.level_time - self.level_time_vector.duration().as_seconds_f32();
+        !(-Self::MAX_LEVEL_TIME_MISMATCH..=Self::MAX_LEVEL_TIME_MISMATCH).contains(&difference)
+    }
+
+    /// The level time is available both in seconds and as the digits shown on
+    /// screen. If they disagree, the digits are what the runner sees, so they
+    /// are preferred.
+    fn checked_level_time(&self) -> Duration {
+        if self.is_level_time_mismatched() {
+            self.level_time_vector.duration()
+        } else {
+            Duration::saturating_seconds_f32(self.level_time)
+        }
+    }
+}
+
 #[allow(unused)]
 mod game_state {
     pub const MISSION: i32 = 0;
@@ -286,126 +1359,382 @@ mod game_state {
     pub const RESPAWN: i32 = 5;
     pub const RESULTS: i32 = 6;
     pub const LOAD: i32 = 7;
-}
 
-impl Timer {
-    fn character(&self) -> &'static str {
-        match self.character {
-            0 => "Hana",
-            1 => "Toree",
-            2 => "Toukie",
-            3 => "Accel",
+    pub fn name(state: i32) -> &'static str {
+        match state {
+            MISSION => "Mission",
+            TITLE_SCREEN => "Title Screen",
+            MENU => "Menu",
+            CUTSCENE => "Cutscene",
+            DEATH => "Death",
+            RESPAWN => "Respawn",
+            RESULTS => "Results",
+            LOAD => "Load",
             _ => "Unknown",
         }
     }
 }
 
-fn read_string(process: &Process, ptr: Address64) -> Option<ArrayString<16>> {
-    let len = process.read::<u32>(ptr + 0x10).ok()? as usize;
-    let utf16_buf = &mut [0u16; 16][..len.min(16)];
-    let mut utf8_buf = ArrayString::<16>::new();
-    process.read_into_slice(ptr + 0x14, utf16_buf).ok()?;
+/// The layout of the header of a managed `System.String` in 64-bit IL2CPP.
+mod il2cpp_string {
+    pub const LENGTH: u64 = 0x10;
+    pub const CHARS: u64 = 0x14;
+}
+
+fn read_string<const N: usize>(process: &Process, ptr: Address64) -> Option<ArrayString<N>> {
+    let len = process.read::<u32>(ptr + il2cpp_string::LENGTH).ok()? as usize;
+    let utf16_buf = &mut [0u16; N][..len.min(N)];
+    let mut utf8_buf = ArrayString::<N>::new();
+    process
+        .read_into_slice(ptr + il2cpp_string::CHARS, utf16_buf)
+        .ok()?;
     for c in char::decode_utf16(utf16_buf.iter().copied()) {
         let _ = utf8_buf.try_push(c.unwrap_or(char::REPLACEMENT_CHARACTER));
     }
     Some(utf8_buf)
 }
 
+/// Linux only keeps the first 15 bytes of a process name, which also applies to
+/// games running through Proton or Wine.
+const MAX_LINUX_PROCESS_NAME_LEN: usize = 15;
+
+/// The names the game's process might show up as. Besides the name being cut
+/// off on Linux, the executable might have been lowercased along the way.
+fn process_name_variants(name: &str) -> ArrayVec<ArrayString<32>, 4> {
+    let mut lowercase = ArrayString::<32>::new();
+    for c in name.chars() {
+        let _ = lowercase.try_push(c.to_ascii_lowercase());
+    }
+
+    let mut variants = ArrayVec::<ArrayString<32>, 4>::new();
+    for name in [name, &lowercase] {
+        let truncated = name.get(..MAX_LINUX_PROCESS_NAME_LEN).unwrap_or(name);
+        for variant in [name, truncated] {
+            if !variants.iter().any(|v| v.as_str() == variant) {
+                if let Ok(variant) = ArrayString::from(variant) {
+                    variants.push(variant);
+                }
+            }
+        }
+    }
+    variants
+}
+
+const DEMO_PROCESS_NAME: &str = "Lunistice-Demo.exe";
+const PROCESS_NAMES: [&str; 2] = ["Lunistice.exe", DEMO_PROCESS_NAME];
+
+/// Attaches to the game. If multiple instances are running, the first one that
+/// already has the game's code loaded is preferred. Otherwise the most recently
+/// listed one is chosen, as that's usually the one that was started last. The
+/// Process variable shows which of the instances was chosen.
+fn attach() -> Option<(Process, &'static str)> {
+    let mut chosen = None;
+    let mut chosen_is_loaded = false;
+    let mut instances = 0;
+    // Several of the name variants can list the same process.
+    let mut seen = ArrayVec::<ProcessId, 32>::new();
+
+    for name in PROCESS_NAMES {
+        for pid in process_name_variants(name)
+            .iter()
+            .flat_map(|variant| Process::list_by_name(variant))
+        {
+            if seen.contains(&pid) {
+                continue;
+            }
+            let _ = seen.try_push(pid);
+            let Some(process) = Process::attach_by_pid(pid) else {
+                continue;
+            };
+            instances += 1;
+            // The rest are only counted.
+            if chosen_is_loaded {
+                continue;
+            }
+            chosen_is_loaded = process.get_module_address("GameAssembly.dll").is_ok();
+            chosen = Some((process, name, instances));
+        }
+    }
+
+    let (process, name, instance) = chosen?;
+
+    if instances > 1 {
+        print_message("Multiple instances of the game are running");
+    }
+
+    let mut buffer = itoa::Buffer::new();
+    let mut string = ArrayString::<48>::new();
+    let _ = string.try_push_str(name);
+    if instances > 1 {
+        let _ = string.try_push_str(" (");
+        let _ = string.try_push_str(buffer.format(instance));
+        let _ = string.try_push_str(" of ");
+        let _ = string.try_push_str(buffer.format(instances));
+        let _ = string.try_push(')');
+    }
+    timer::set_variable("Process", &string);
+
+    Some((process, name))
+}
+
+/// The variables that were sent to the timer last, so only the ones that
+/// changed get sent again.
+#[derive(Default)]
+struct Variables {
+    values: ArrayVec<(&'static str, ArrayString<32>), 48>,
+}
+
+impl Variables {
+    fn set(&mut self, key: &'static str, value: &str) {
+        let index = self.values.iter().position(|(k, _)| *k == key);
+        if let Some(index) = index {
+            if self.values[index].1.as_str() == value {
+                return;
+            }
+        }
+        timer::set_variable(key, value);
+        // Values that are too long to remember are just sent every time.
+        match (index, ArrayString::from(value)) {
+            (Some(index), Ok(value)) => self.values[index].1 = value,
+            (Some(index), Err(_)) => {
+                self.values.swap_remove(index);
+            }
+            (None, Ok(value)) => {
+                let _ = self.values.try_push((key, value));
+            }
+            (None, Err(_)) => {}
+        }
+    }
+}
+
+/// Everything the variables are based on, collected over the tick.
+struct Snapshot<'a> {
+    game_manager: &'a GameManager,
+    timer: &'a Timer,
+    pauses: &'a Pauses,
+    state_times: &'a StateTimes,
+    stale: bool,
+    remaining: Option<Remaining>,
+    milestone: &'a Milestone,
+    total_igt: Option<Duration>,
+}
+
+/// Publishes all the variables at once at the end of the tick, after all the
+/// state they are based on has been updated, so they are consistent with each
+/// other.
+fn publish_variables(snapshot: Snapshot, variables: &mut Variables) {
+    let Snapshot {
+        game_manager,
+        timer,
+        pauses,
+        state_times,
+        stale,
+        remaining,
+        milestone,
+        total_igt,
+    } = snapshot;
+    let mut buffer = itoa::Buffer::new();
+    variables.set("Game State", game_state::name(game_manager.game_state));
+    variables.set("Points", buffer.format(game_manager.points));
+    variables.set("Resets", buffer.format(game_manager.deaths));
+
+    let mut string_buffer = ArrayString::<32>::new();
+    timer.level_time_vector.format_into(&mut string_buffer);
+    variables.set("Level Time", &string_buffer);
+    match total_igt {
+        Some(total_igt) => {
+            string_buffer.clear();
+            Digits::from(total_igt).format_into(&mut string_buffer);
+            variables.set("Total IGT", &string_buffer);
+        }
+        None => variables.set("Total IGT", "-"),
+    }
+    game_manager
+        .level_or_scene
+        .set_variable(&mut string_buffer, variables);
+    variables.set("Character", formatting::character(timer.character));
+
+    state_times.set_variables(&mut string_buffer, variables);
+    pauses.set_variables(&mut string_buffer, variables);
+
+    variables.set("Stale", if stale { "Yes" } else { "No" });
+    variables.set("Milestone", milestone.text().unwrap_or("-"));
+
+    match remaining {
+        Some(remaining) => {
+            variables.set("Levels Remaining", buffer.format(remaining.levels));
+            match remaining.estimate {
+                Some(estimate) => {
+                    string_buffer.clear();
+                    Digits::from(estimate).format_into(&mut string_buffer);
+                    variables.set("ETA", &string_buffer);
+                }
+                None => variables.set("ETA", "-"),
+            }
+        }
+        None => {
+            variables.set("Levels Remaining", "-");
+            variables.set("ETA", "-");
+        }
+    }
+}
+
+fn publish_status(game_manager: &GameManager, timer: &Timer, total_igt: Option<Duration>) {
+    let mut status = formatting::Status::<64>::new();
+    match &game_manager.level_or_scene {
+        LevelOrScene::Level(level) => formatting::level_into(*level, status.piece()),
+        LevelOrScene::Scene(scene) => {
+            let _ = status.piece().try_push_str(scene);
+        }
+    }
+    let _ = status
+        .piece()
+        .try_push_str(formatting::character(timer.character));
+    let piece = status.piece();
+    let _ = piece.try_push_str(itoa::Buffer::new().format(game_manager.deaths));
+    let _ = piece.try_push_str(if game_manager.deaths == 1 {
+        " death"
+    } else {
+        " deaths"
+    });
+    if let Some(total_igt) = total_igt {
+        Digits::from(total_igt).format_into(status.piece());
+    }
+    timer::set_variable("Status", status.as_str());
+}
+
 #[cfg(not(feature = "nightly"))]
 asr::async_main!(stable);
 #[cfg(feature = "nightly")]
 asr::async_main!(nightly);
 
 async fn main() {
-    let mut run_time = Duration::ZERO;
-    let mut beyond_first_level = false;
+    let mut settings = Settings::register();
+    let mut run = Run::default();
+    let mut controller = Cooperative::new(AsrTimer);
+    let mut best_level_times = BestLevelTimes::default();
+    let mut milestone = Milestone::default();
+    let mut pauses = Pauses::default();
+    let mut state_times = StateTimes::default();
+    let mut variables = Variables::default();
 
     loop {
         asr::set_tick_rate(1.0);
 
-        let process = retry(|| {
-            Process::attach("Lunistice.exe").or_else(|| Process::attach("Lunistice-Demo.exe"))
-        })
-        .await;
+        let (process, process_name) = retry(attach).await;
 
         process
             .until_closes(async {
-                let game_info = GameInfo::load(&process).await;
+                let mut game_info =
+                    GameInfo::load(&process, process_name == DEMO_PROCESS_NAME).await;
 
                 let mut timer = Watcher::new();
                 let mut game_manager = Watcher::new();
                 let mut timer_state = Watcher::new();
+                let mut last_timer = HoldLast::default();
+                let mut last_game_manager = HoldLast::default();
+                let mut status_throttle = Throttle::default();
+                let mut reload_throttle = Throttle::default();
+                let mut warned_about_7_x = false;
 
                 asr::set_tick_rate(120.0);
 
                 loop {
+                    settings.update();
+
+                    if reload_throttle.ready(Duration::SECOND)
+                        && game_info.is_outdated(
+                            &process,
+                            last_game_manager.is_lost() && last_timer.is_lost(),
+                        )
+                    {
+                        print_message("The game was reloaded, binding everything again");
+                        asr::set_tick_rate(1.0);
+                        game_info =
+                            GameInfo::load(&process, process_name == DEMO_PROCESS_NAME).await;
+                        asr::set_tick_rate(120.0);
+                        timer = Watcher::new();
+                        game_manager = Watcher::new();
+                        last_timer = HoldLast::default();
+                        last_game_manager = HoldLast::default();
+                    }
+
                     let game_manager = game_manager.update(
-                        game_info
-                            .game_manager_class
-                            .read(&process, game_info.game_manager_instance)
-                            .ok(),
+                        last_game_manager.update(game_info.read_game_manager(&process).ok()),
                     );
 
                     let timer = timer.update(
-                        game_info
-                            .timer_class
-                            .read(&process, game_info.timer_instance)
-                            .ok(),
+                        last_timer.update(
+                            game_info
+                                .timer_class
+                                .read(&process, game_info.timer_instance)
+                                .ok(),
+                        ),
                     );
 
                     if let (Some(game_manager), Some(timer)) = (game_manager, timer) {
-                        let mut buffer = itoa::Buffer::new();
-                        timer::set_variable("Points", buffer.format(game_manager.points));
-                        timer::set_variable("Resets", buffer.format(game_manager.deaths));
-
-                        let mut string_buffer = ArrayString::<32>::new();
-                        timer.level_time_vector.format_into(&mut string_buffer);
-                        timer::set_variable("Level Time", &string_buffer);
-                        game_manager.level_or_scene.set_variable(&mut string_buffer);
-                        timer::set_variable("Character", timer.character());
+                        state_times.update(
+                            game_manager.game_state,
+                            game_manager.old.level_or_scene != game_manager.current.level_or_scene,
+                        );
 
                         let timer_state = timer_state.update_infallible(timer::state());
 
-                        // We do this here because the runner might start the
-                        // timer themselves.
+                        if settings.split_entering_7_x()
+                            && !warned_about_7_x
+                            && game_manager.level_or_scene.level().is_none()
+                        {
+                            print_message(
+                                "Splitting on entering 7-X isn't supported with the DLC update",
+                            );
+                            warned_about_7_x = true;
+                        }
+
                         if timer_state.changed_from(&TimerState::NotRunning) {
-                            run_time = Duration::ZERO;
-                            beyond_first_level = false;
-                            timer::pause_game_time();
-                            timer::set_game_time(run_time);
+                            pauses = Pauses::default();
                         }
 
-                        match timer_state.current {
-                            TimerState::NotRunning => {
-                                if timer.check(|t| !t.timer_stopped)
-                                    && game_manager.level_or_scene.is_in_first_level()
-                                {
-                                    timer::start();
-                                }
-                            }
-                            TimerState::Paused | TimerState::Running => {
-                                if timer.current.level_time < timer.old.level_time {
-                                    if !beyond_first_level {
-                                        timer::reset();
-                                        return;
-                                    }
-                                    run_time +=
-                                        Duration::saturating_seconds_f32(timer.old.level_time);
-                                }
+                        let mut remaining = None;
+                        let mut total_igt = None;
+
+                        controller.observe(settings.cooperate, timer_state);
+                        run.update(&settings, timer_state, game_manager, timer, &mut controller);
 
-                                timer::set_game_time(
-                                    run_time + Duration::saturating_seconds_f32(timer.level_time),
-                                );
+                        if matches!(
+                            timer_state.current,
+                            TimerState::Paused | TimerState::Running
+                        ) {
+                            pauses.update(game_manager.game_state == game_state::MENU);
+                            total_igt = Some(run.game_time(timer));
 
+                            if let Some(level) = game_manager.level_or_scene.level() {
                                 if game_manager.check(|g| g.game_state == game_state::RESULTS)
-                                    || (game_manager.old.level_or_scene.is_in_final_level()
-                                        && game_manager.current.level_or_scene.is_in_credits())
+                                    && best_level_times.record(level, timer.checked_level_time())
+                                    && settings.milestones
                                 {
-                                    beyond_first_level = true;
-                                    timer::split();
+                                    milestone.gold(level);
                                 }
                             }
-                            _ => {}
+                            remaining =
+                                run.remaining(&settings, &best_level_times, game_manager, timer);
+                        }
+
+                        publish_variables(
+                            Snapshot {
+                                game_manager,
+                                timer,
+                                pauses: &pauses,
+                                state_times: &state_times,
+                                stale: last_game_manager.is_stale() || last_timer.is_stale(),
+                                remaining,
+                                milestone: &milestone,
+                                total_igt,
+                            },
+                            &mut variables,
+                        );
+
+                        if settings.status && status_throttle.ready(Duration::SECOND) {
+                            publish_status(game_manager, timer, total_igt);
                         }
                     }
 
@@ -0,0 +1,1312 @@
+//! Decides when to start, split and reset based on what was read from the
+//! game. It only controls the timer through [`TimerController`], so it can be
+//! tested on the host.
+
+use asr::{print_message, time::Duration, timer, timer::TimerState, watcher::Pair};
+
+use crate::{
+    game_state, BestLevelTimes, CompletedLevels, Credits, GameManager, LevelOrScene, OnDeath,
+    Remaining, RepeatedLevels, Settings, Timer, Timing,
+};
+
+pub trait TimerController {
+    fn start(&mut self);
+    fn split(&mut self);
+    fn undo_split(&mut self);
+    fn reset(&mut self);
+    fn set_game_time(&mut self, time: Duration);
+    fn pause_game_time(&mut self);
+    fn resume_game_time(&mut self);
+}
+
+/// Controls the timer of the auto splitting runtime.
+pub struct AsrTimer;
+
+impl TimerController for AsrTimer {
+    fn start(&mut self) {
+        timer::start();
+    }
+
+    fn split(&mut self) {
+        timer::split();
+    }
+
+    fn undo_split(&mut self) {
+        timer::undo_split();
+    }
+
+    fn reset(&mut self) {
+        timer::reset();
+    }
+
+    fn set_game_time(&mut self, time: Duration) {
+        timer::set_game_time(time);
+    }
+
+    fn pause_game_time(&mut self) {
+        timer::pause_game_time();
+    }
+
+    fn resume_game_time(&mut self) {
+        timer::resume_game_time();
+    }
+}
+
+/// Notices when something else keeps starting and resetting the timer, such as
+/// a second auto splitter, and stops fighting it over that.
+pub struct Cooperative<C> {
+    inner: C,
+    ticks: u32,
+    issued: Option<TimerState>,
+    /// The last change of the timer we caused and when it happened.
+    own_change: Option<(TimerState, u32)>,
+    last_external_change: u32,
+    last_contested_change: u32,
+    contested_changes: u32,
+    cooperating: bool,
+}
+
+impl<C: TimerController> Cooperative<C> {
+    /// A quarter of a second at the regular tick rate, which is faster than
+    /// a person can react.
+    const REACTION_TICKS: u32 = 30;
+    /// Ten seconds at the regular tick rate.
+    const WINDOW_TICKS: u32 = 1200;
+    const MAX_CONTESTED_CHANGES: u32 = 2;
+    /// Five minutes at the regular tick rate.
+    const QUIET_TICKS: u32 = 36000;
+
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            ticks: 0,
+            issued: None,
+            own_change: None,
+            last_external_change: 0,
+            last_contested_change: 0,
+            contested_changes: 0,
+            cooperating: false,
+        }
+    }
+
+    /// Checks whether the timer got started or reset without us asking for
+    /// it. The runner does that by hand all the time, but undoing what we
+    /// just did within a fraction of a second means something else is
+    /// controlling the timer. Once nothing else touched the timer for a
+    /// while, we take over again.
+    pub fn observe(&mut self, enabled: bool, timer_state: &Pair<TimerState>) {
+        self.ticks = self.ticks.wrapping_add(1);
+        if !enabled {
+            self.cooperating = false;
+            self.contested_changes = 0;
+            return;
+        }
+        if self.cooperating
+            && self.ticks.wrapping_sub(self.last_external_change) > Self::QUIET_TICKS
+        {
+            print_message(
+                "Nothing else controlled the timer for a while, starting and resetting again",
+            );
+            self.cooperating = false;
+            self.contested_changes = 0;
+        }
+        if timer_state.old == timer_state.current {
+            return;
+        }
+        let expected = match (timer_state.old, timer_state.current) {
+            (TimerState::NotRunning, _) => TimerState::Running,
+            (_, TimerState::NotRunning) => TimerState::NotRunning,
+            _ => return,
+        };
+        if self.issued.take() == Some(expected) {
+            self.own_change = Some((expected, self.ticks));
+            return;
+        }
+        self.last_external_change = self.ticks;
+        let contested = self.own_change.take().is_some_and(|(state, at)| {
+            state != expected && self.ticks.wrapping_sub(at) <= Self::REACTION_TICKS
+        });
+        if !contested {
+            return;
+        }
+        if self.ticks.wrapping_sub(self.last_contested_change) > Self::WINDOW_TICKS {
+            self.contested_changes = 0;
+        }
+        self.last_contested_change = self.ticks;
+        self.contested_changes += 1;
+        if self.contested_changes >= Self::MAX_CONTESTED_CHANGES && !self.cooperating {
+            print_message("Something else controls the timer, no longer starting or resetting");
+            self.cooperating = true;
+        }
+    }
+}
+
+impl<C: TimerController> TimerController for Cooperative<C> {
+    fn start(&mut self) {
+        if !self.cooperating {
+            self.issued = Some(TimerState::Running);
+            self.inner.start();
+        }
+    }
+
+    fn split(&mut self) {
+        self.inner.split();
+    }
+
+    fn undo_split(&mut self) {
+        self.inner.undo_split();
+    }
+
+    fn reset(&mut self) {
+        if !self.cooperating {
+            self.issued = Some(TimerState::NotRunning);
+            self.inner.reset();
+        }
+    }
+
+    fn set_game_time(&mut self, time: Duration) {
+        self.inner.set_game_time(time);
+    }
+
+    fn pause_game_time(&mut self) {
+        self.inner.pause_game_time();
+    }
+
+    fn resume_game_time(&mut self) {
+        self.inner.resume_game_time();
+    }
+}
+
+/// The state of the current run.
+pub struct Run {
+    run_time: Duration,
+    beyond_first_level: bool,
+    next_level: i32,
+    died_in_level: bool,
+    split_in_level: bool,
+    deaths: u32,
+    level_counted: bool,
+    /// How much of the level timer ran during cutscenes in the levels that
+    /// count towards the run.
+    cutscene_time: Duration,
+    /// The same for the current attempt at the level.
+    level_cutscene_time: Duration,
+    completed_levels: CompletedLevels,
+    credits_started: bool,
+    game_time_frozen: bool,
+    game_time_paused: bool,
+}
+
+impl Default for Run {
+    fn default() -> Self {
+        Self {
+            run_time: Duration::ZERO,
+            beyond_first_level: false,
+            next_level: LevelOrScene::LEVEL_1_1,
+            died_in_level: false,
+            split_in_level: false,
+            deaths: 0,
+            level_counted: false,
+            cutscene_time: Duration::ZERO,
+            level_cutscene_time: Duration::ZERO,
+            completed_levels: CompletedLevels::default(),
+            credits_started: false,
+            game_time_frozen: false,
+            game_time_paused: true,
+        }
+    }
+}
+
+impl Run {
+    /// The game time of the levels that are already finished.
+    pub fn run_time(&self) -> Duration {
+        self.run_time
+    }
+
+    /// How much of the run is left. The credits share their level with 2-1, so
+    /// once they started there's nothing left to estimate.
+    pub fn remaining(
+        &self,
+        settings: &Settings,
+        best_level_times: &BestLevelTimes,
+        game_manager: &GameManager,
+        timer: &Timer,
+    ) -> Option<Remaining> {
+        if self.credits_started {
+            return None;
+        }
+        best_level_times.remaining(
+            settings,
+            game_manager.level_or_scene.level()?,
+            self.run_time(),
+            timer.checked_level_time(),
+        )
+    }
+
+    /// The game time of the run including the level that is being played.
+    pub fn game_time(&self, timer: &Timer) -> Duration {
+        let game_time = if self.level_counted {
+            self.run_time
+        } else {
+            self.run_time + timer.checked_level_time()
+        };
+        game_time.saturating_sub(self.cutscene_time + self.level_cutscene_time)
+    }
+
+    pub fn update(
+        &mut self,
+        settings: &Settings,
+        timer_state: &Pair<TimerState>,
+        game_manager: &Pair<GameManager>,
+        timer: &Pair<Timer>,
+        controller: &mut impl TimerController,
+    ) {
+        // We do this here because the runner might start the timer themselves.
+        if timer_state.changed_from(&TimerState::NotRunning) {
+            *self = Self::default();
+            controller.pause_game_time();
+            controller.set_game_time(self.run_time);
+        }
+
+        match timer_state.current {
+            TimerState::NotRunning => {
+                if timer.check(|t| !t.timer_stopped)
+                    && game_manager.level_or_scene.is_in_first_level()
+                    && settings.start_character.allows(timer.current.character)
+                {
+                    controller.start();
+                }
+            }
+            TimerState::Paused | TimerState::Running => {
+                self.update_running(settings, game_manager, timer, controller)
+            }
+            _ => {}
+        }
+    }
+
+    fn update_running(
+        &mut self,
+        settings: &Settings,
+        game_manager: &Pair<GameManager>,
+        timer: &Pair<Timer>,
+        controller: &mut impl TimerController,
+    ) {
+        if game_manager.old.level_or_scene != game_manager.current.level_or_scene {
+            self.died_in_level = false;
+            self.split_in_level = false;
+            // The first level doesn't necessarily split, so leaving it is what
+            // tells us that the level timer restarting isn't a new attempt.
+            if !game_manager.level_or_scene.is_in_first_level() {
+                self.beyond_first_level = true;
+            }
+            // Playing on after the credits, like 7-X in All Stages, picks the
+            // game time back up.
+            if self.credits_started && !game_manager.level_or_scene.is_in_credits() {
+                self.credits_started = false;
+                self.game_time_frozen = false;
+            }
+        } else if matches!(
+            game_manager.game_state,
+            game_state::DEATH | game_state::RESPAWN
+        ) {
+            self.died_in_level = true;
+        } else if game_manager.game_state == game_state::RESULTS {
+            // Dying along the way doesn't matter once the level is finished.
+            self.died_in_level = false;
+        }
+
+        if timer.current.level_time < timer.old.level_time {
+            if !self.beyond_first_level && settings.restart_first_level.resets(game_manager) {
+                controller.reset();
+                return;
+            }
+            // A quick restart resets the level time without changing the
+            // level. The aborted attempt doesn't count towards the run.
+            if !self.died_in_level && matches!(settings.timing, Timing::LevelTime) {
+                self.run_time += timer.old.checked_level_time();
+                self.level_counted = true;
+            }
+            // Neither do its cutscenes.
+            if self.level_counted {
+                self.cutscene_time += self.level_cutscene_time;
+            }
+            self.level_cutscene_time = Duration::ZERO;
+            self.died_in_level = false;
+            self.level_counted = false;
+        }
+
+        // The official time of the level only shows up on the results screen,
+        // so until then the game time follows the level timer.
+        if matches!(settings.timing, Timing::ResultTimes)
+            && !self.level_counted
+            && game_manager.check(|g| g.game_state == game_state::RESULTS)
+        {
+            self.run_time += timer.level_time_vector.duration();
+            self.level_counted = true;
+        }
+
+        let in_cutscene =
+            settings.pause_in_cutscenes && game_manager.game_state == game_state::CUTSCENE;
+
+        if in_cutscene {
+            let elapsed = timer.current.checked_level_time() - timer.old.checked_level_time();
+            if elapsed.is_positive() {
+                self.level_cutscene_time += elapsed;
+            }
+        }
+
+        if timer.check(|t| t.is_level_time_mismatched()) {
+            print_message("The level time disagrees with the digits shown");
+        }
+
+        let game_time = self.game_time(timer);
+
+        let run_finished = game_manager.old.level_or_scene.is_in_final_level()
+            && game_manager.current.level_or_scene.is_in_credits();
+
+        if run_finished {
+            self.credits_started = true;
+        }
+
+        // The credits usually end the run, after which the timer doesn't ask
+        // us for the game time anymore, so it's stopped right away and stays
+        // that way until the credits are left.
+        let freeze_game_time = self.credits_started;
+
+        if matches!(settings.timing, Timing::RealTimeWithoutLoads) {
+            // The timer keeps the game time running on its own, we only pause
+            // it during loads.
+            let pause =
+                freeze_game_time || game_manager.game_state == game_state::LOAD || in_cutscene;
+            if pause != self.game_time_paused {
+                if pause {
+                    controller.pause_game_time();
+                } else {
+                    controller.resume_game_time();
+                }
+                self.game_time_paused = pause;
+            }
+        } else if !self.game_time_frozen {
+            if freeze_game_time {
+                // Only the final time gets quantized, so the game time keeps
+                // updating smoothly until then.
+                controller.set_game_time(settings.final_time.apply(game_time));
+                self.game_time_frozen = true;
+            } else {
+                controller.set_game_time(game_time);
+            }
+        }
+
+        if game_manager.current.deaths > game_manager.old.deaths {
+            self.deaths += 1;
+            match settings.on_death {
+                OnDeath::Nothing => {}
+                OnDeath::Split => controller.split(),
+                OnDeath::Reset => {
+                    controller.reset();
+                    return;
+                }
+                OnDeath::UndoSplit => {
+                    if self.split_in_level {
+                        self.undo_split(game_manager, controller);
+                    }
+                }
+            }
+            if settings.reset_after_deaths.is_reached(self.deaths) {
+                controller.reset();
+                return;
+            }
+        }
+
+        if settings.split_entering_7_x() && game_manager.check(|g| g.level_or_scene.is_in_7_x()) {
+            controller.split();
+        }
+
+        if game_manager.old.level_or_scene != game_manager.current.level_or_scene {
+            if let LevelOrScene::Scene(scene) = &game_manager.level_or_scene {
+                if settings.split_entering_scene(scene) {
+                    controller.split();
+                }
+            }
+        }
+
+        let finished = settings.split_trigger.finished(game_manager);
+        let any_level_finished = settings
+            .split_trigger
+            .is_level_finished(game_manager, timer);
+
+        let level_finished = any_level_finished
+            && (settings.split_bonus_stages() || finished.level_or_scene.bonus_stage().is_none())
+            && finished
+                .level_or_scene
+                .level()
+                .is_none_or(|level| settings.splits_level(level));
+
+        // The DLC's scenes don't have a fixed order, so there's nothing to
+        // check them against.
+        let in_order = match finished.level_or_scene.level() {
+            Some(level) if settings.split_in_order => level == self.next_level,
+            _ => true,
+        };
+
+        if level_finished && !in_order {
+            print_message("Ignoring a level finished out of order");
+        }
+
+        // Levels that don't split still count as finished, so the ones after
+        // them are expected next.
+        if any_level_finished {
+            self.died_in_level = false;
+            if finished.level_or_scene.level() == Some(self.next_level) {
+                self.next_level += 1;
+            }
+        }
+
+        let mut should_split = (level_finished && in_order)
+            || (run_finished && !matches!(settings.credits, Credits::VariablesOnly));
+
+        if should_split && level_finished && !self.completed_levels.insert(&finished.level_or_scene)
+        {
+            should_split = matches!(settings.repeated_levels, RepeatedLevels::Split);
+        }
+
+        if should_split {
+            self.beyond_first_level = true;
+            self.split_in_level = level_finished;
+            controller.split();
+        }
+    }
+
+    /// Takes back the split of the current level, so it counts as unfinished
+    /// again.
+    fn undo_split(
+        &mut self,
+        game_manager: &Pair<GameManager>,
+        controller: &mut impl TimerController,
+    ) {
+        self.completed_levels.remove(&game_manager.level_or_scene);
+        if let Some(level) = game_manager.level_or_scene.level() {
+            self.next_level = level;
+        }
+        self.split_in_level = false;
+        controller.undo_split();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrayvec::{ArrayString, ArrayVec};
+    use asr::settings::gui::Title;
+
+    use super::*;
+    use crate::{
+        formatting::{self, Digits},
+        Category, DeathLimit, FinalTime, RestartFirstLevel, SplitTrigger, StartCharacter,
+    };
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum Event {
+        Start,
+        Split,
+        UndoSplit,
+        Reset,
+        SetGameTime(Duration),
+        PauseGameTime,
+        ResumeGameTime,
+    }
+
+    /// Records what the run did to the timer instead of controlling one.
+    #[derive(Default)]
+    struct Recorder {
+        events: ArrayVec<Event, 64>,
+    }
+
+    impl Recorder {
+        /// The events that actually control the run, leaving out the game time
+        /// updates that happen on every tick.
+        fn actions(&self) -> ArrayVec<Event, 64> {
+            self.events
+                .iter()
+                .copied()
+                .filter(|e| !matches!(e, Event::SetGameTime(_)))
+                .collect()
+        }
+    }
+
+    impl TimerController for Recorder {
+        fn start(&mut self) {
+            self.events.push(Event::Start);
+        }
+
+        fn split(&mut self) {
+            self.events.push(Event::Split);
+        }
+
+        fn undo_split(&mut self) {
+            self.events.push(Event::UndoSplit);
+        }
+
+        fn reset(&mut self) {
+            self.events.push(Event::Reset);
+        }
+
+        fn set_game_time(&mut self, time: Duration) {
+            self.events.push(Event::SetGameTime(time));
+        }
+
+        fn pause_game_time(&mut self) {
+            self.events.push(Event::PauseGameTime);
+        }
+
+        fn resume_game_time(&mut self) {
+            self.events.push(Event::ResumeGameTime);
+        }
+    }
+
+    fn settings() -> Settings {
+        Settings {
+            category: Category::Custom,
+            start_character: StartCharacter::Any,
+            timing: Timing::LevelTime,
+            pause_in_cutscenes: false,
+            split_trigger: SplitTrigger::Results,
+            split_in_order: false,
+            split_stages_only: false,
+            repeated_levels: RepeatedLevels::Split,
+            split_bonus_stages: true,
+            on_death: OnDeath::Nothing,
+            reset_after_deaths: DeathLimit::Never,
+            restart_first_level: RestartFirstLevel::Reset,
+            split_entering_7_x: false,
+            milestones: false,
+            status: false,
+            credits: Credits::Stop,
+            final_time: FinalTime::Exact,
+            cooperate: false,
+            levels: Title,
+            split_1_1: true,
+            split_1_2: true,
+            split_2_1: true,
+            split_2_2: true,
+            split_3_1: true,
+            split_3_2: true,
+            split_4_1: true,
+            split_4_2: true,
+            split_5_1: true,
+            split_5_2: true,
+            split_6_1: true,
+            split_6_2: true,
+            split_7_1: true,
+            split_7_2: true,
+            split_7_x: true,
+            dlc_scenes: Title,
+            enter_shrine_02: false,
+            enter_shrine_03: false,
+            enter_shrine_04: false,
+            enter_shrine_05: false,
+            enter_shrine_06: false,
+            enter_shrine_07: false,
+            enter_toree_01: false,
+            enter_toree_02: false,
+            enter_toree_03: false,
+        }
+    }
+
+    fn game_manager(game_state: i32, level: i32) -> GameManager {
+        GameManager {
+            game_state,
+            points: 0,
+            deaths: 0,
+            level_or_scene: LevelOrScene::Level(level),
+        }
+    }
+
+    fn scene(game_state: i32, scene: &str) -> GameManager {
+        GameManager {
+            level_or_scene: LevelOrScene::Scene(ArrayString::from(scene).unwrap()),
+            ..game_manager(game_state, 0)
+        }
+    }
+
+    fn timer(level_time: f32, timer_stopped: bool) -> Timer {
+        Timer {
+            level_time,
+            level_time_vector: Digits::from(Duration::saturating_seconds_f32(level_time)),
+            timer_stopped,
+            character: 0,
+        }
+    }
+
+    /// Feeds the run one tick after another, like the main loop does.
+    struct Harness {
+        settings: Settings,
+        run: Run,
+        recorder: Recorder,
+        timer_state: Pair<TimerState>,
+        game_manager: Pair<GameManager>,
+        timer: Pair<Timer>,
+        /// The number of splits after which the run ends.
+        segments: Option<usize>,
+    }
+
+    impl Harness {
+        fn new(settings: Settings, game_manager: GameManager, timer: Timer) -> Self {
+            Self {
+                settings,
+                run: Run::default(),
+                recorder: Recorder::default(),
+                timer_state: Pair {
+                    old: TimerState::NotRunning,
+                    current: TimerState::NotRunning,
+                },
+                game_manager: Pair {
+                    old: game_manager,
+                    current: game_manager,
+                },
+                timer: Pair {
+                    old: timer,
+                    current: timer,
+                },
+                segments: None,
+            }
+        }
+
+        fn tick(&mut self, game_manager: GameManager, timer: Timer) {
+            self.game_manager = Pair {
+                old: self.game_manager.current,
+                current: game_manager,
+            };
+            self.timer = Pair {
+                old: self.timer.current,
+                current: timer,
+            };
+            self.run.update(
+                &self.settings,
+                &self.timer_state,
+                &self.game_manager,
+                &self.timer,
+                &mut self.recorder,
+            );
+            // The runtime reacts to the run controlling the timer by the next
+            // tick.
+            let splits = self
+                .recorder
+                .events
+                .iter()
+                .filter(|e| **e == Event::Split)
+                .count();
+            let next_state = match self.recorder.events.last() {
+                Some(Event::Start) => TimerState::Running,
+                Some(Event::Reset) => TimerState::NotRunning,
+                Some(Event::Split) if Some(splits) == self.segments => TimerState::Ended,
+                _ => self.timer_state.current,
+            };
+            self.timer_state = Pair {
+                old: self.timer_state.current,
+                current: next_state,
+            };
+        }
+
+        /// Starts the run in 1-1 and plays it until the level timer is at the
+        /// given time.
+        fn start(settings: Settings) -> Self {
+            let mut harness = Self::new(
+                settings,
+                game_manager(game_state::MISSION, 0),
+                timer(0.0, true),
+            );
+            harness.tick(game_manager(game_state::MISSION, 0), timer(0.0, false));
+            harness.tick(game_manager(game_state::MISSION, 0), timer(1.0, false));
+            harness
+        }
+
+        /// Finishes the level, going through the results screen into the next
+        /// one.
+        fn finish(&mut self, level: i32, time: f32) {
+            self.tick(game_manager(game_state::MISSION, level), timer(time, true));
+            self.tick(game_manager(game_state::RESULTS, level), timer(time, true));
+            self.tick(game_manager(game_state::LOAD, level + 1), timer(0.0, true));
+            self.tick(
+                game_manager(game_state::MISSION, level + 1),
+                timer(0.0, false),
+            );
+        }
+    }
+
+    #[test]
+    fn starts_when_the_first_level_timer_starts() {
+        let harness = Harness::start(settings());
+        assert_eq!(
+            harness.recorder.actions().as_slice(),
+            [Event::Start, Event::PauseGameTime]
+        );
+    }
+
+    #[test]
+    fn only_starts_for_the_selected_character() {
+        let settings = Settings {
+            start_character: StartCharacter::Toree,
+            ..settings()
+        };
+        let harness = Harness::start(settings);
+        assert!(harness.recorder.actions().is_empty());
+    }
+
+    #[test]
+    fn splits_on_the_results_screen() {
+        let mut harness = Harness::start(settings());
+        harness.finish(0, 30.0);
+        harness.finish(1, 45.0);
+        assert_eq!(
+            harness.recorder.actions().as_slice(),
+            [
+                Event::Start,
+                Event::PauseGameTime,
+                Event::Split,
+                Event::Split
+            ]
+        );
+        assert_eq!(harness.run.run_time(), Duration::seconds(75));
+    }
+
+    #[test]
+    fn counts_levels_finished_after_dying() {
+        let mut harness = Harness::start(settings());
+        harness.finish(0, 30.0);
+        let died = GameManager {
+            deaths: 1,
+            ..game_manager(game_state::DEATH, 1)
+        };
+        harness.tick(died, timer(10.0, false));
+        harness.tick(game_manager(game_state::MISSION, 1), timer(20.0, false));
+        harness.tick(game_manager(game_state::MISSION, 1), timer(40.0, true));
+        harness.tick(game_manager(game_state::RESULTS, 1), timer(40.0, true));
+        // The level timer resets just before the next level is loaded.
+        harness.tick(game_manager(game_state::LOAD, 1), timer(0.0, true));
+        harness.tick(game_manager(game_state::LOAD, 2), timer(0.0, true));
+        assert_eq!(harness.run.run_time(), Duration::seconds(70));
+    }
+
+    #[test]
+    fn pauses_the_game_time_in_cutscenes() {
+        let settings = Settings {
+            pause_in_cutscenes: true,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        harness.tick(game_manager(game_state::MISSION, 0), timer(10.0, false));
+        harness.tick(game_manager(game_state::CUTSCENE, 0), timer(15.0, false));
+        harness.tick(game_manager(game_state::CUTSCENE, 0), timer(20.0, false));
+        harness.tick(game_manager(game_state::MISSION, 0), timer(25.0, false));
+        assert_eq!(
+            harness.recorder.events.last(),
+            Some(&Event::SetGameTime(Duration::seconds(15)))
+        );
+    }
+
+    #[test]
+    fn discards_the_cutscenes_of_an_attempt_that_died() {
+        let settings = Settings {
+            pause_in_cutscenes: true,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        harness.finish(0, 10.0);
+        harness.tick(game_manager(game_state::CUTSCENE, 1), timer(5.0, false));
+        harness.tick(game_manager(game_state::DEATH, 1), timer(5.0, false));
+        harness.tick(game_manager(game_state::MISSION, 1), timer(0.0, false));
+        harness.tick(game_manager(game_state::MISSION, 1), timer(3.0, false));
+        assert_eq!(
+            harness.recorder.events.last(),
+            Some(&Event::SetGameTime(Duration::seconds(13)))
+        );
+    }
+
+    #[test]
+    fn pauses_the_real_time_in_cutscenes() {
+        let settings = Settings {
+            timing: Timing::RealTimeWithoutLoads,
+            pause_in_cutscenes: true,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        harness.tick(game_manager(game_state::CUTSCENE, 0), timer(2.0, false));
+        harness.tick(game_manager(game_state::MISSION, 0), timer(3.0, false));
+        assert_eq!(
+            harness.recorder.actions().as_slice(),
+            [
+                Event::Start,
+                Event::PauseGameTime,
+                Event::ResumeGameTime,
+                Event::PauseGameTime,
+                Event::ResumeGameTime
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_when_the_timer_stops() {
+        let settings = Settings {
+            split_trigger: SplitTrigger::TimerStopped,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        harness.tick(game_manager(game_state::MISSION, 0), timer(30.0, true));
+        assert_eq!(harness.recorder.actions().last(), Some(&Event::Split));
+    }
+
+    #[test]
+    fn splits_when_leaving_the_results_screen() {
+        let settings = Settings {
+            split_trigger: SplitTrigger::ResultsExit,
+            split_in_order: true,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        harness.tick(game_manager(game_state::MISSION, 0), timer(30.0, true));
+        harness.tick(game_manager(game_state::RESULTS, 0), timer(30.0, true));
+        assert!(!harness.recorder.actions().contains(&Event::Split));
+        harness.tick(game_manager(game_state::LOAD, 1), timer(0.0, true));
+        assert_eq!(harness.recorder.actions().last(), Some(&Event::Split));
+        harness.finish(1, 45.0);
+        harness.tick(game_manager(game_state::LOAD, 3), timer(0.0, true));
+        assert_eq!(
+            harness.recorder.actions().as_slice(),
+            [
+                Event::Start,
+                Event::PauseGameTime,
+                Event::Split,
+                Event::Split
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_disabled_levels() {
+        let settings = Settings {
+            split_1_2: false,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        harness.finish(0, 30.0);
+        harness.finish(1, 45.0);
+        assert_eq!(
+            harness.recorder.actions().as_slice(),
+            [Event::Start, Event::PauseGameTime, Event::Split]
+        );
+    }
+
+    #[test]
+    fn splits_at_the_end_of_each_stage() {
+        let settings = Settings {
+            split_stages_only: true,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        harness.finish(0, 30.0);
+        harness.finish(1, 45.0);
+        harness.finish(2, 40.0);
+        assert_eq!(
+            harness.recorder.actions().as_slice(),
+            [Event::Start, Event::PauseGameTime, Event::Split]
+        );
+    }
+
+    #[test]
+    fn keeps_the_order_when_levels_dont_split() {
+        let settings = Settings {
+            split_in_order: true,
+            split_stages_only: true,
+            split_2_2: false,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        for (level, time) in [
+            (0, 30.0),
+            (1, 45.0),
+            (2, 40.0),
+            (3, 50.0),
+            (4, 35.0),
+            (5, 55.0),
+        ] {
+            harness.finish(level, time);
+        }
+        assert_eq!(
+            harness.recorder.actions().as_slice(),
+            [
+                Event::Start,
+                Event::PauseGameTime,
+                Event::Split,
+                Event::Split
+            ]
+        );
+    }
+
+    #[test]
+    fn any_percent_splits_every_level_up_to_7_2() {
+        let settings = Settings {
+            category: Category::AnyPercent,
+            split_stages_only: true,
+            split_1_1: false,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        harness.finish(0, 30.0);
+        harness.finish(formatting::LEVEL_7_X, 60.0);
+        assert_eq!(
+            harness.recorder.actions().as_slice(),
+            [Event::Start, Event::PauseGameTime, Event::Split]
+        );
+    }
+
+    #[test]
+    fn resets_when_restarting_the_first_level() {
+        let mut harness = Harness::start(settings());
+        harness.tick(game_manager(game_state::MISSION, 0), timer(0.0, false));
+        assert_eq!(harness.recorder.actions().last(), Some(&Event::Reset));
+    }
+
+    #[test]
+    fn keeps_running_when_restarting_the_first_level() {
+        let settings = Settings {
+            restart_first_level: RestartFirstLevel::KeepRunning,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        harness.tick(game_manager(game_state::MISSION, 0), timer(0.0, false));
+        assert_eq!(
+            harness.recorder.actions().as_slice(),
+            [Event::Start, Event::PauseGameTime]
+        );
+    }
+
+    #[test]
+    fn only_resets_when_restarting_from_scratch() {
+        let settings = Settings {
+            restart_first_level: RestartFirstLevel::ResetFromScratch,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        let scored = GameManager {
+            points: 20,
+            ..game_manager(game_state::MISSION, 0)
+        };
+        harness.tick(scored, timer(0.0, false));
+        assert!(!harness.recorder.actions().contains(&Event::Reset));
+        harness.tick(scored, timer(1.0, false));
+        harness.tick(game_manager(game_state::MISSION, 0), timer(0.0, false));
+        assert_eq!(harness.recorder.actions().last(), Some(&Event::Reset));
+    }
+
+    #[test]
+    fn ignores_repeated_levels() {
+        let settings = Settings {
+            repeated_levels: RepeatedLevels::Ignore,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        harness.finish(0, 30.0);
+        harness.finish(0, 30.0);
+        harness.finish(1, 30.0);
+        assert_eq!(
+            harness.recorder.actions().as_slice(),
+            [
+                Event::Start,
+                Event::PauseGameTime,
+                Event::Split,
+                Event::Split
+            ]
+        );
+    }
+
+    #[test]
+    fn undoes_the_split_when_dying_in_the_same_level() {
+        let settings = Settings {
+            split_trigger: SplitTrigger::TimerStopped,
+            on_death: OnDeath::UndoSplit,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        harness.tick(game_manager(game_state::MISSION, 0), timer(30.0, true));
+        let died = GameManager {
+            deaths: 1,
+            ..game_manager(game_state::DEATH, 0)
+        };
+        harness.tick(died, timer(30.0, true));
+        assert_eq!(
+            harness.recorder.actions().as_slice(),
+            [
+                Event::Start,
+                Event::PauseGameTime,
+                Event::Split,
+                Event::UndoSplit
+            ]
+        );
+    }
+
+    #[test]
+    fn resets_only_once_when_dying() {
+        let settings = Settings {
+            on_death: OnDeath::Reset,
+            reset_after_deaths: DeathLimit::Once,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        let died = GameManager {
+            deaths: 1,
+            ..game_manager(game_state::DEATH, 0)
+        };
+        harness.tick(died, timer(1.0, false));
+        assert_eq!(
+            harness.recorder.actions().as_slice(),
+            [Event::Start, Event::PauseGameTime, Event::Reset]
+        );
+    }
+
+    #[test]
+    fn resets_after_too_many_deaths() {
+        let settings = Settings {
+            reset_after_deaths: DeathLimit::Three,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        for deaths in 1..=3 {
+            assert!(!harness.recorder.actions().contains(&Event::Reset));
+            let died = GameManager {
+                deaths,
+                ..game_manager(game_state::DEATH, 0)
+            };
+            harness.tick(died, timer(1.0, false));
+        }
+        assert_eq!(harness.recorder.actions().last(), Some(&Event::Reset));
+    }
+
+    #[test]
+    fn splits_on_entering_dlc_scenes() {
+        let settings = Settings {
+            enter_shrine_02: true,
+            ..settings()
+        };
+        let mut harness = Harness::new(
+            settings,
+            scene(game_state::MISSION, "Shrine01"),
+            timer(0.0, true),
+        );
+        harness.tick(scene(game_state::MISSION, "Shrine01"), timer(0.0, false));
+        harness.tick(scene(game_state::MISSION, "Shrine01"), timer(30.0, true));
+        harness.tick(scene(game_state::LOAD, "Shrine02"), timer(30.0, true));
+        harness.tick(scene(game_state::MISSION, "Shrine02"), timer(0.0, false));
+        harness.tick(scene(game_state::LOAD, "Shrine03"), timer(40.0, true));
+        assert_eq!(
+            harness.recorder.actions().as_slice(),
+            [Event::Start, Event::PauseGameTime, Event::Split]
+        );
+    }
+
+    #[test]
+    fn cooperates_with_another_auto_splitter() {
+        let mut controller = Cooperative::new(Recorder::default());
+        let mut state = TimerState::NotRunning;
+        let mut change = |controller: &mut Cooperative<Recorder>, current| {
+            controller.observe(
+                true,
+                &Pair {
+                    old: state,
+                    current,
+                },
+            );
+            state = current;
+        };
+
+        // The runner starting and resetting by hand, no matter how often, is
+        // fine.
+        for _ in 0..5 {
+            change(&mut controller, TimerState::Running);
+            change(&mut controller, TimerState::NotRunning);
+        }
+        controller.start();
+        change(&mut controller, TimerState::Running);
+        controller.reset();
+        change(&mut controller, TimerState::NotRunning);
+        assert_eq!(
+            controller.inner.events.as_slice(),
+            [Event::Start, Event::Reset]
+        );
+
+        // Something else immediately undoes our resets.
+        for _ in 0..2 {
+            controller.start();
+            change(&mut controller, TimerState::Running);
+            controller.reset();
+            change(&mut controller, TimerState::NotRunning);
+            change(&mut controller, TimerState::Running);
+        }
+        controller.reset();
+        controller.split();
+        assert_eq!(
+            controller.inner.events.as_slice()[2..],
+            [
+                Event::Start,
+                Event::Reset,
+                Event::Start,
+                Event::Reset,
+                Event::Split
+            ]
+        );
+
+        // Once it has been quiet for a while, we take over again.
+        for _ in 0..=Cooperative::<Recorder>::QUIET_TICKS {
+            change(&mut controller, TimerState::Running);
+        }
+        controller.reset();
+        assert_eq!(controller.inner.events.last(), Some(&Event::Reset));
+    }
+
+    #[test]
+    fn ignores_bonus_stages() {
+        let settings = Settings {
+            split_bonus_stages: false,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        harness.tick(scene(game_state::MISSION, "Toree01"), timer(2.0, false));
+        harness.tick(scene(game_state::RESULTS, "Toree01"), timer(10.0, true));
+        assert_eq!(
+            harness.recorder.actions().as_slice(),
+            [Event::Start, Event::PauseGameTime]
+        );
+    }
+
+    #[test]
+    fn stops_the_game_time_in_the_credits() {
+        let mut harness = Harness::start(settings());
+        harness.tick(
+            game_manager(game_state::MISSION, LevelOrScene::LEVEL_7_2),
+            timer(20.0, false),
+        );
+        harness.tick(
+            game_manager(game_state::CUTSCENE, LevelOrScene::LEVEL_2_1),
+            timer(20.0, true),
+        );
+        harness.tick(
+            game_manager(game_state::CUTSCENE, LevelOrScene::LEVEL_2_1),
+            timer(25.0, true),
+        );
+        assert_eq!(harness.recorder.actions().last(), Some(&Event::Split));
+        let game_time = harness
+            .recorder
+            .events
+            .iter()
+            .rev()
+            .find(|e| matches!(e, Event::SetGameTime(_)));
+        assert_eq!(game_time, Some(&Event::SetGameTime(Duration::seconds(20))));
+    }
+
+    #[test]
+    fn keeps_the_game_time_going_after_the_credits() {
+        let mut harness = Harness::start(settings());
+        harness.tick(
+            game_manager(game_state::MISSION, LevelOrScene::LEVEL_7_2),
+            timer(20.0, false),
+        );
+        harness.tick(
+            game_manager(game_state::CUTSCENE, LevelOrScene::LEVEL_2_1),
+            timer(20.0, true),
+        );
+        harness.tick(
+            game_manager(game_state::LOAD, formatting::LEVEL_7_X),
+            timer(0.0, true),
+        );
+        harness.tick(
+            game_manager(game_state::MISSION, formatting::LEVEL_7_X),
+            timer(5.0, false),
+        );
+        assert_eq!(
+            harness.recorder.events.last(),
+            Some(&Event::SetGameTime(Duration::seconds(25)))
+        );
+    }
+
+    #[test]
+    fn estimates_the_rest_of_the_run() {
+        let mut best_level_times = BestLevelTimes::default();
+        for level in LevelOrScene::LEVEL_1_1..=formatting::LEVEL_7_X {
+            best_level_times.record(level, Duration::seconds(10));
+        }
+        let remaining = |harness: &Harness| {
+            harness
+                .run
+                .remaining(
+                    &harness.settings,
+                    &best_level_times,
+                    &harness.game_manager,
+                    &harness.timer,
+                )
+                .map(|remaining| (remaining.levels, remaining.estimate))
+        };
+
+        let mut harness = Harness::start(settings());
+        assert_eq!(
+            remaining(&harness),
+            Some((15, Some(Duration::seconds(150))))
+        );
+
+        let settings = Settings {
+            category: Category::AnyPercent,
+            ..settings()
+        };
+        let mut any_percent = Harness::start(settings);
+        assert_eq!(
+            remaining(&any_percent),
+            Some((14, Some(Duration::seconds(140))))
+        );
+
+        for harness in [&mut harness, &mut any_percent] {
+            harness.tick(
+                game_manager(game_state::MISSION, LevelOrScene::LEVEL_7_2),
+                timer(20.0, false),
+            );
+            harness.tick(
+                game_manager(game_state::CUTSCENE, LevelOrScene::LEVEL_2_1),
+                timer(20.0, true),
+            );
+            assert_eq!(remaining(harness), None);
+        }
+    }
+
+    #[test]
+    fn applies_the_final_time_before_the_run_ends() {
+        let settings = Settings {
+            final_time: FinalTime::Round,
+            ..settings()
+        };
+        let mut harness = Harness::start(settings);
+        harness.segments = Some(1);
+        harness.tick(
+            game_manager(game_state::MISSION, LevelOrScene::LEVEL_7_2),
+            timer(20.006, false),
+        );
+        harness.tick(
+            game_manager(game_state::CUTSCENE, LevelOrScene::LEVEL_2_1),
+            timer(20.006, true),
+        );
+        assert_eq!(harness.timer_state.current, TimerState::Ended);
+        assert_eq!(harness.recorder.events.last(), Some(&Event::Split));
+        let events = harness.recorder.events.len();
+        harness.tick(
+            game_manager(game_state::CUTSCENE, LevelOrScene::LEVEL_2_1),
+            timer(25.0, true),
+        );
+        assert_eq!(harness.recorder.events.len(), events);
+        let game_time = harness
+            .recorder
+            .events
+            .iter()
+            .rev()
+            .find(|e| matches!(e, Event::SetGameTime(_)));
+        assert_eq!(
+            game_time,
+            Some(&Event::SetGameTime(Duration::milliseconds(20010)))
+        );
+    }
+}